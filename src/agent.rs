@@ -1,12 +1,17 @@
+use crate::checkpoint::CheckpointStore;
+use crate::client::{ApiRequest, ChatClient};
 use crate::config::Config;
 use crate::file_state_manager::FileStateManager;
+use crate::lsp::LspManager;
+use crate::process_manager::ProcessManager;
 use crate::prompt_builder;
-use crate::streaming_executor;
+use crate::streaming_executor::{self, RetryPolicy};
+use crate::system_prompt::SystemPromptTemplate;
 use crate::tool_collection::ToolCollection;
+use crate::worktree::Worktree;
 use anyhow::Result;
 use openrouter_api::models::tool::ToolCall;
 use openrouter_api::types::chat::{ChatCompletionRequest, Message};
-use openrouter_api::{OpenRouterClient, Ready};
 use std::sync::{Arc, Mutex};
 use tokio::task::JoinHandle;
 
@@ -15,6 +20,8 @@ pub struct PromptData {
     pub final_prompt: String,
     pub file_summaries: Vec<String>,
     pub warnings: Vec<String>,
+    /// The instruction files attached to the system prompt.
+    pub instruction_files: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -31,22 +38,59 @@ pub enum AgentOutput {
 }
 
 pub struct Agent {
-    pub client: Option<Arc<OpenRouterClient<Ready>>>,
+    pub client: Option<Arc<ChatClient>>,
     pub config: Config,
     pub messages: Vec<Message>,
     pub tool_collection: Arc<ToolCollection>,
     pub file_state_manager: Arc<Mutex<FileStateManager>>,
+    /// Background processes started by the agent. Shared with the `manage_processes` tool.
+    pub process_manager: Arc<Mutex<ProcessManager>>,
+    /// Running language servers. Shared with the `language_server` tool.
+    pub lsp_manager: Arc<Mutex<LspManager>>,
+    /// Git checkpoints of this session, if `checkpoints` is enabled and the cwd is a git repo.
+    pub checkpoints: Option<CheckpointStore>,
+    /// The temporary worktree the session runs in, with `--worktree`.
+    pub worktree: Option<Worktree>,
+    /// The template of the system message, which is the first message if it is set.
+    pub system_prompt: Option<SystemPromptTemplate>,
 }
 
 impl Agent {
     /// Creates a new `Agent` with its own state.
     pub fn new(
         config: Config,
-        client: Option<OpenRouterClient<Ready>>,
+        client: Option<ChatClient>,
         tool_collection: Arc<ToolCollection>,
+    ) -> Self {
+        let process_manager = Arc::new(Mutex::new(ProcessManager::new(config.process_log_lines)));
+        let lsp_manager = Arc::new(Mutex::new(LspManager::new(
+            std::env::current_dir().unwrap_or_default(),
+        )));
+        Self::with_managers(
+            config,
+            client,
+            tool_collection,
+            process_manager,
+            lsp_manager,
+        )
+    }
+
+    /// Creates a new `Agent` that shares the process and language server managers with the
+    /// tools that use them, like `manage_processes` and `language_server`.
+    pub fn with_managers(
+        config: Config,
+        client: Option<ChatClient>,
+        tool_collection: Arc<ToolCollection>,
+        process_manager: Arc<Mutex<ProcessManager>>,
+        lsp_manager: Arc<Mutex<LspManager>>,
     ) -> Self {
         Self {
             client: client.map(Arc::new),
+            process_manager,
+            lsp_manager,
+            checkpoints: None,
+            worktree: None,
+            system_prompt: None,
             config,
             messages: Vec::new(),
             file_state_manager: Arc::new(Mutex::new(FileStateManager::new())),
@@ -54,6 +98,13 @@ impl Agent {
         }
     }
 
+    /// Stops all background processes and language servers. Must be called before the
+    /// program exits, because `std::process::exit` does not run destructors.
+    pub fn shutdown(&self) {
+        self.process_manager.lock().unwrap().stop_all();
+        self.lsp_manager.lock().unwrap().stop_all();
+    }
+
     /// Processes a raw user prompt, expanding file paths and generating context.
     /// This method does NOT modify the agent's message history.
     pub fn prepare_prompt(&self, prompt: &str) -> Result<PromptData> {
@@ -63,17 +114,67 @@ impl Agent {
             final_prompt: prompt_data.final_prompt,
             file_summaries: prompt_data.file_summaries,
             warnings: prompt_data.warnings,
+            instruction_files: prompt_data.instruction_files,
         })
     }
 
+    /// Renders the system prompt template, expands its `@` references and attaches the
+    /// instruction files. Returns the rendered template before expansion and the final prompt.
+    pub fn render_system_prompt(&self) -> Result<Option<(String, PromptData)>> {
+        let Some(template) = &self.system_prompt else {
+            return Ok(None);
+        };
+        let dir = std::env::current_dir()?;
+        let rendered = template.render(&self.config, &dir);
+        let mut fsm = self.file_state_manager.lock().unwrap();
+        let prompt_data =
+            prompt_builder::process_system_prompt(&rendered, &self.config, &mut fsm, &dir)?;
+        Ok(Some((
+            rendered,
+            PromptData {
+                final_prompt: prompt_data.final_prompt,
+                file_summaries: prompt_data.file_summaries,
+                warnings: prompt_data.warnings,
+                instruction_files: prompt_data.instruction_files,
+            },
+        )))
+    }
+
+    /// Renders the system message again if its template file changed since it was read.
+    fn reload_system_prompt(&mut self) -> Result<()> {
+        let Some(template) = &mut self.system_prompt else {
+            return Ok(());
+        };
+        if !template.reload_if_changed()? {
+            return Ok(());
+        }
+        let path = template.path().map(|path| path.display().to_string());
+        if let Some((_, prompt_data)) = self.render_system_prompt()?
+            && let Some(message) = self.messages.first_mut()
+            && message.role == "system"
+        {
+            message.content = prompt_data.final_prompt;
+            println!(
+                "{}",
+                console::style(format!(
+                    "Reloaded the system prompt from {}.",
+                    path.unwrap_or_default()
+                ))
+                .dim()
+            );
+        }
+        Ok(())
+    }
+
     /// Takes a user prompt, runs the LLM, and returns a handle to the streaming task.
     pub fn step_streaming(&mut self, prompt: String) -> Result<AgentOutput> {
         let request = self.prepare_request(prompt)?;
 
         if let Some(request) = request {
             let client = self.client.as_ref().unwrap().clone();
+            let policy = RetryPolicy::from_config(&self.config);
             let handle = tokio::spawn(async move {
-                streaming_executor::stream_and_collect_response(&client, request).await
+                streaming_executor::stream_and_collect_response(&client, request, &policy).await
             });
             Ok(AgentOutput::PendingLLM(handle))
         } else {
@@ -87,8 +188,10 @@ impl Agent {
 
         if let Some(request) = request {
             let client = self.client.as_ref().unwrap().clone();
+            let policy = RetryPolicy::from_config(&self.config);
             let response =
-                streaming_executor::collect_response_non_streaming(&client, request).await?;
+                streaming_executor::collect_response_non_streaming(&client, request, &policy)
+                    .await?;
             if let Some(message) = response {
                 self.messages.push(message.clone());
                 if let Some(tool_calls) = message.tool_calls {
@@ -109,7 +212,7 @@ impl Agent {
     pub async fn execute_tool_calls(&mut self, tool_calls: Vec<ToolCall>) -> Result<Vec<Message>> {
         let mut result_messages = Vec::new();
         for tool_call in tool_calls {
-            let result_msg = self
+            let (result_msg, _) = self
                 .tool_collection
                 .execute_tool_call(&tool_call, &self.config, self.file_state_manager.clone())
                 .await;
@@ -121,7 +224,12 @@ impl Agent {
 
     // --- Private Helper Functions ---
 
-    fn prepare_request(&mut self, prompt: String) -> Result<Option<ChatCompletionRequest>> {
+    fn prepare_request(&mut self, prompt: String) -> Result<Option<ApiRequest>> {
+        // A broken template file keeps the previous system message.
+        if let Err(e) = self.reload_system_prompt() {
+            eprintln!("{}", console::style(format!("[Warning] {e}")).yellow());
+        }
+
         if !prompt.is_empty() {
             let user_message = Message {
                 role: "user".to_string(),
@@ -138,8 +246,8 @@ impl Agent {
         }
 
         let tools = self.tool_collection.get_all_schemas();
-        let request = ChatCompletionRequest {
-            model: self.config.model.clone(),
+        let completion = ChatCompletionRequest {
+            model: self.config.model_id(),
             messages: self.messages.clone(),
             tools: Some(tools),
             stream: Some(true), // This will be overridden in the non-streaming case
@@ -148,6 +256,28 @@ impl Agent {
             models: None,
             transforms: None,
         };
+        let request = ApiRequest {
+            completion,
+            parameters: self.config.model_parameters(),
+        };
+
+        if let Some(context_window) = self
+            .config
+            .model_config()
+            .and_then(|model_config| model_config.context_window)
+        {
+            let tokens = estimate_tokens(&self.messages);
+            if tokens > context_window {
+                eprintln!(
+                    "{}",
+                    console::style(format!(
+                        "[Warning] The conversation has ~{tokens} tokens, more than the context window of {context_window} tokens of {}.",
+                        self.config.model
+                    ))
+                    .yellow()
+                );
+            }
+        }
 
         if self.config.print_messages {
             println!();
@@ -168,3 +298,20 @@ impl Agent {
         Ok(Some(request))
     }
 }
+
+/// Estimates the number of tokens of messages, at about 4 characters per token.
+fn estimate_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| {
+            message.content.len()
+                + message
+                    .tool_calls
+                    .iter()
+                    .flatten()
+                    .map(|tool_call| tool_call.function_call.arguments.len())
+                    .sum::<usize>()
+        })
+        .sum::<usize>()
+        .div_ceil(4)
+}
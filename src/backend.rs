@@ -1,34 +1,141 @@
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct BackendConfig {
     pub base_url: String,
-    pub api_key_env_var: Option<&'static str>,
+    pub api_key_env_var: Option<String>,
+    /// Extra HTTP headers sent with each request.
+    pub headers: BTreeMap<String, String>,
+    /// Short model names mapped to the model IDs of the backend.
+    pub models: BTreeMap<String, String>,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+/// An OpenAI-compatible provider defined in a `[providers.<name>]` config table, like a vLLM,
+/// LM Studio or llama.cpp server, or an internal gateway. It is selected with `backend = "<name>"`.
+/// A table named like a built-in backend extends it, e.g. with headers.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProviderConfig {
+    /// The base URL of the API, e.g. `http://localhost:8000/v1/`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub base_url: String,
+    /// The environment variable holding the API key. Without one, no key is sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env_var: Option<String>,
+    /// Extra HTTP headers. `${VAR}` in a value is replaced by the environment variable `VAR`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Short model names mapped to the model IDs of the provider.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, String>,
+}
+
+/// Settings of a model, defined in a `[models.<alias>]` config table and used when
+/// `model` is the alias. Unset parameters are left to the API's defaults.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ModelConfig {
+    /// The model ID sent to the API. Defaults to the alias.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// The reasoning effort of reasoning models, e.g. `low`, `medium` or `high`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    /// The size of the context window in tokens. A warning is shown when a request exceeds it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Backend {
     #[default]
     Openrouter,
     Ollama,
     Openai,
+    /// A provider defined in a `[providers.<name>]` config table.
+    #[serde(untagged)]
+    Custom(String),
 }
 
 impl Backend {
-    pub fn config(&self) -> BackendConfig {
+    pub const BUILT_IN: [Backend; 3] = [Backend::Openrouter, Backend::Ollama, Backend::Openai];
+
+    /// Returns the configuration of a built-in backend, or `None` for a custom provider.
+    pub fn config(&self) -> Option<BackendConfig> {
+        let (base_url, api_key_env_var) = match self {
+            Backend::Openrouter => ("https://openrouter.ai/api/v1/", Some("OPENROUTER_API_KEY")),
+            Backend::Ollama => ("http://localhost:11434/v1/", None),
+            Backend::Openai => ("https://api.openai.com/v1/", Some("OPENAI_API_KEY")),
+            Backend::Custom(_) => return None,
+        };
+        Some(BackendConfig {
+            base_url: base_url.to_string(),
+            api_key_env_var: api_key_env_var.map(str::to_string),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the configuration of this backend, extended or defined by `providers`.
+    pub fn resolve(&self, providers: &BTreeMap<String, ProviderConfig>) -> Result<BackendConfig> {
+        let provider = providers.get(&self.to_string());
+        let mut config = match (self.config(), provider) {
+            (Some(config), _) => config,
+            (None, Some(_)) => BackendConfig::default(),
+            (None, None) => {
+                return Err(anyhow!(
+                    "Unknown backend '{self}'. Define it in a [providers.{self}] table in the config file."
+                ));
+            }
+        };
+        if let Some(provider) = provider {
+            if !provider.base_url.is_empty() {
+                config.base_url = provider.base_url.clone();
+            }
+            if provider.api_key_env_var.is_some() {
+                config.api_key_env_var = provider.api_key_env_var.clone();
+            }
+            config.headers.extend(provider.headers.clone());
+            config.models.extend(provider.models.clone());
+        }
+        if config.base_url.is_empty() {
+            return Err(anyhow!("Provider '{self}' has no base_url."));
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Backend::Openrouter => BackendConfig {
-                base_url: "https://openrouter.ai/api/v1/".to_string(),
-                api_key_env_var: Some("OPENROUTER_API_KEY"),
-            },
-            Backend::Ollama => BackendConfig {
-                base_url: "http://localhost:11434/v1/".to_string(),
-                api_key_env_var: None,
-            },
-            Backend::Openai => BackendConfig {
-                base_url: "https://api.openai.com/v1/".to_string(),
-                api_key_env_var: Some("OPENAI_API_KEY"),
-            },
+            Backend::Openrouter => write!(f, "openrouter"),
+            Backend::Ollama => write!(f, "ollama"),
+            Backend::Openai => write!(f, "openai"),
+            Backend::Custom(name) => write!(f, "{name}"),
         }
     }
 }
+
+impl FromStr for Backend {
+    type Err = std::convert::Infallible;
+
+    /// Parses a built-in backend name. Any other name refers to a custom provider.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(Backend::BUILT_IN
+            .into_iter()
+            .find(|backend| backend.to_string() == name)
+            .unwrap_or_else(|| Backend::Custom(name.to_string())))
+    }
+}
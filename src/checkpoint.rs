@@ -0,0 +1,449 @@
+//! # Git Checkpoints
+//!
+//! When `checkpoints` is enabled, every agent turn that changed files is recorded as a
+//! commit on a shadow ref like `refs/alors/session-<id>`. The user's index, HEAD and
+//! branches are never touched: snapshots are built with a temporary index file.
+//!
+//! Checkpoints can be listed, diffed against the working tree and restored with the
+//! `/checkpoint` REPL commands. Unlike the in-memory `FileStateManager`, they survive
+//! a crash and can be inspected with plain git.
+
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The usage of the `/checkpoint` REPL command.
+pub const USAGE: &str = "Usage: /checkpoint list | /checkpoint diff <n> | /checkpoint restore <n>";
+
+/// A single recorded checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The 1-indexed number of the checkpoint in this session.
+    pub number: usize,
+    pub commit: String,
+    pub message: String,
+}
+
+/// Records checkpoints of the working tree of one repository on a session ref.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    root: PathBuf,
+    session_ref: String,
+    /// The commit HEAD pointed to when the session started, if any.
+    base: Option<String>,
+}
+
+impl CheckpointStore {
+    /// Opens the store for the repository containing `dir`.
+    /// Returns `None` if `dir` is not inside a git repository.
+    pub fn open(dir: &Path, session_id: &str) -> Result<Option<Self>> {
+        let Ok(root) = git(dir, &["rev-parse", "--show-toplevel"], None) else {
+            return Ok(None);
+        };
+        let root = PathBuf::from(root.trim());
+        let base = git(&root, &["rev-parse", "--verify", "-q", "HEAD"], None)
+            .ok()
+            .map(|commit| commit.trim().to_string());
+        Ok(Some(Self {
+            root,
+            session_ref: format!("refs/alors/session-{session_id}"),
+            base,
+        }))
+    }
+
+    /// Creates a session id from the current time and process id.
+    pub fn new_session_id() -> String {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        format!("{seconds}-{}", std::process::id())
+    }
+
+    pub fn session_ref(&self) -> &str {
+        &self.session_ref
+    }
+
+    /// Records the current working tree as a checkpoint.
+    /// Returns `None` if nothing changed since the last checkpoint.
+    pub fn record(&self, message: &str) -> Result<Option<Checkpoint>> {
+        let parent = self.tip().or_else(|| self.base.clone());
+        let tree = self.snapshot_tree()?;
+        if let Some(parent) = &parent {
+            let parent_tree = git(
+                &self.root,
+                &["rev-parse", &format!("{parent}^{{tree}}")],
+                None,
+            )?;
+            if parent_tree.trim() == tree {
+                return Ok(None);
+            }
+        }
+
+        let number = self.list()?.len() + 1;
+        let message = format!("alors checkpoint {number}: {}", first_line(message));
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message.as_str()];
+        if let Some(parent) = &parent {
+            args.extend(["-p", parent.as_str()]);
+        }
+        let commit = git(&self.root, &args, None)?.trim().to_string();
+        git(
+            &self.root,
+            &["update-ref", &self.session_ref, &commit],
+            None,
+        )?;
+        Ok(Some(Checkpoint {
+            number,
+            commit,
+            message,
+        }))
+    }
+
+    /// Lists the checkpoints of this session, oldest first.
+    pub fn list(&self) -> Result<Vec<Checkpoint>> {
+        let Some(tip) = self.tip() else {
+            return Ok(Vec::new());
+        };
+        let range = match &self.base {
+            Some(base) => format!("{base}..{tip}"),
+            None => tip,
+        };
+        let output = git(
+            &self.root,
+            &["log", "--reverse", "--format=%H%x09%s", &range],
+            None,
+        )?;
+        Ok(output
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .enumerate()
+            .map(|(i, (commit, message))| Checkpoint {
+                number: i + 1,
+                commit: commit.to_string(),
+                message: message.to_string(),
+            })
+            .collect())
+    }
+
+    /// Shows the changes of the working tree since a checkpoint, including untracked files.
+    pub fn diff(&self, number: usize) -> Result<String> {
+        let checkpoint = self.get(number)?;
+        let tree = self.snapshot_tree()?;
+        git(
+            &self.root,
+            &["diff", "--stat", "--patch", &checkpoint.commit, &tree],
+            None,
+        )
+    }
+
+    /// Restores the working tree to a checkpoint. Files created after it are removed.
+    ///
+    /// The current state is recorded as a new checkpoint first, so a restore can be undone.
+    /// Returns the paths that were changed, relative to the repository root.
+    pub fn restore(&self, number: usize) -> Result<Vec<String>> {
+        let checkpoint = self.get(number)?;
+        self.record(&format!("before restoring checkpoint {number}"))?;
+        let tree = self.snapshot_tree()?;
+
+        let changed = git(
+            &self.root,
+            &[
+                "diff",
+                "--name-status",
+                "--no-renames",
+                &checkpoint.commit,
+                &tree,
+            ],
+            None,
+        )?;
+        let mut paths = Vec::new();
+        for line in changed.lines() {
+            let Some((status, path)) = line.split_once('\t') else {
+                continue;
+            };
+            // Files that did not exist at the checkpoint are removed, all others are checked out.
+            if status == "A" {
+                std::fs::remove_file(self.root.join(path))?;
+            }
+            paths.push(path.to_string());
+        }
+
+        let index = TempIndex::new()?;
+        git(
+            &self.root,
+            &["read-tree", &checkpoint.commit],
+            Some(index.path()),
+        )?;
+        git(
+            &self.root,
+            &["checkout-index", "--all", "--force"],
+            Some(index.path()),
+        )?;
+        Ok(paths)
+    }
+
+    fn get(&self, number: usize) -> Result<Checkpoint> {
+        let checkpoints = self.list()?;
+        checkpoints
+            .get(number.wrapping_sub(1))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Checkpoint {number} does not exist. There are {} checkpoints.",
+                    checkpoints.len()
+                )
+            })
+    }
+
+    fn tip(&self) -> Option<String> {
+        git(
+            &self.root,
+            &["rev-parse", "--verify", "-q", &self.session_ref],
+            None,
+        )
+        .ok()
+        .map(|commit| commit.trim().to_string())
+    }
+
+    /// Writes the working tree, including untracked but not ignored files, as a tree object.
+    fn snapshot_tree(&self) -> Result<String> {
+        let index = TempIndex::new()?;
+        git(&self.root, &["add", "--all", "."], Some(index.path()))?;
+        Ok(git(&self.root, &["write-tree"], Some(index.path()))?
+            .trim()
+            .to_string())
+    }
+}
+
+/// Renders the checkpoint list for the REPL.
+pub fn format_list(checkpoints: &[Checkpoint]) -> String {
+    if checkpoints.is_empty() {
+        return "No checkpoints yet.".to_string();
+    }
+    checkpoints
+        .iter()
+        .map(|checkpoint| {
+            format!(
+                "{:>3}  {}  {}",
+                checkpoint.number,
+                &checkpoint.commit[..8.min(checkpoint.commit.len())],
+                checkpoint.message
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The output of a `/checkpoint` REPL command.
+#[derive(Debug)]
+pub struct CommandOutput {
+    /// The text shown to the user.
+    pub text: String,
+    /// A note for the model after a restore changed files it may have read.
+    pub note: Option<String>,
+}
+
+/// Returns the arguments of a `/checkpoint` command, or `None` if `input` is not one.
+pub fn parse_command(input: &str) -> Option<&str> {
+    let args = input.trim_start().strip_prefix("/checkpoint")?;
+    (args.is_empty() || args.starts_with(char::is_whitespace)).then_some(args)
+}
+
+/// Runs a `/checkpoint` REPL command and returns its output.
+pub fn run_command(store: &CheckpointStore, args: &str) -> Result<CommandOutput> {
+    let mut parts = args.split_whitespace();
+    let subcommand = parts.next().unwrap_or("list");
+    let number = || -> Result<usize> {
+        parts
+            .clone()
+            .next()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| anyhow!(USAGE))
+    };
+    let text = |text: String| CommandOutput { text, note: None };
+    match subcommand {
+        "list" => Ok(text(format_list(&store.list()?))),
+        "diff" => {
+            let diff = store.diff(number()?)?;
+            Ok(text(if diff.trim().is_empty() {
+                "No changes since this checkpoint.".to_string()
+            } else {
+                diff
+            }))
+        }
+        "restore" => {
+            let number = number()?;
+            let paths = store.restore(number)?;
+            let list = |indent: &str| {
+                paths
+                    .iter()
+                    .map(|path| format!("\n{indent}{path}"))
+                    .collect::<String>()
+            };
+            Ok(CommandOutput {
+                text: format!(
+                    "Restored checkpoint {number} ({} files changed).{}",
+                    paths.len(),
+                    list("  ")
+                ),
+                note: (!paths.is_empty()).then(|| {
+                    format!(
+                        "The user restored the files to checkpoint {number}. These files changed on disk, relative to the repository root. Their earlier contents and LIDs are outdated, read them again before editing them:{}",
+                        list("- ")
+                    )
+                }),
+            })
+        }
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+fn first_line(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > 72 {
+        format!("{}...", line.chars().take(69).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// A temporary index file, so snapshots never touch the user's index.
+struct TempIndex {
+    _dir: tempfile::TempDir,
+    path: PathBuf,
+}
+
+impl TempIndex {
+    fn new() -> Result<Self> {
+        let dir = tempfile::Builder::new().prefix("alors-index").tempdir()?;
+        let path = dir.path().join("index");
+        Ok(Self { _dir: dir, path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Runs git in `dir`, optionally with a separate index file, and returns its stdout.
+pub(crate) fn git(dir: &Path, args: &[&str], index_file: Option<&Path>) -> Result<String> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(dir);
+    if let Some(index_file) = index_file {
+        command.env("GIT_INDEX_FILE", index_file);
+    }
+    let output = command
+        .output()
+        .map_err(|e| anyhow!("Failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    fn setup_repo() -> tempfile::TempDir {
+        let tmp_dir = Builder::new().prefix("test-checkpoint").tempdir().unwrap();
+        let dir = tmp_dir.path();
+        git(dir, &["init", "-q", "-b", "main"], None).unwrap();
+        git(dir, &["config", "user.name", "Tester"], None).unwrap();
+        git(dir, &["config", "user.email", "tester@example.com"], None).unwrap();
+        fs::write(dir.join("lib.rs"), "fn a() {}\n").unwrap();
+        git(dir, &["add", "."], None).unwrap();
+        git(dir, &["commit", "-q", "-m", "Initial commit"], None).unwrap();
+        tmp_dir
+    }
+
+    #[test]
+    fn test_record_list_diff_and_restore() {
+        let tmp_dir = setup_repo();
+        let dir = tmp_dir.path();
+        let head = git(dir, &["rev-parse", "HEAD"], None).unwrap();
+        let store = CheckpointStore::open(dir, "test").unwrap().unwrap();
+
+        assert!(store.record("no changes").unwrap().is_none());
+
+        fs::write(dir.join("lib.rs"), "fn a() { 1 }\n").unwrap();
+        let first = store.record("Change a\nwith details").unwrap().unwrap();
+        assert_eq!(first.number, 1);
+        assert_eq!(first.message, "alors checkpoint 1: Change a");
+
+        fs::write(dir.join("lib.rs"), "fn a() { 2 }\n").unwrap();
+        fs::write(dir.join("new.rs"), "fn b() {}\n").unwrap();
+        store.record("Add b").unwrap().unwrap();
+
+        // HEAD and the index are untouched.
+        assert_eq!(git(dir, &["rev-parse", "HEAD"], None).unwrap(), head);
+        assert_eq!(
+            git(dir, &["diff", "--cached", "--name-only"], None).unwrap(),
+            ""
+        );
+        assert!(git(dir, &["rev-parse", "refs/alors/session-test"], None).is_ok());
+
+        let list = run_command(&store, "list").unwrap().text;
+        assert!(list.contains("  1  "));
+        assert!(list.ends_with("alors checkpoint 2: Add b"));
+
+        let diff = run_command(&store, "diff 1").unwrap().text;
+        assert!(diff.contains("+fn a() { 2 }"));
+        assert!(diff.contains("new.rs"));
+
+        // Changes made after the last checkpoint are recorded before restoring.
+        fs::write(dir.join("lib.rs"), "fn a() { 3 }\n").unwrap();
+        let restored = run_command(&store, "restore 1").unwrap();
+        assert!(
+            restored
+                .text
+                .starts_with("Restored checkpoint 1 (2 files changed).")
+        );
+        let note = restored.note.unwrap();
+        assert!(note.contains("\n- lib.rs"));
+        assert!(note.contains("\n- new.rs"));
+        assert_eq!(
+            fs::read_to_string(dir.join("lib.rs")).unwrap(),
+            "fn a() { 1 }\n"
+        );
+        assert!(!dir.join("new.rs").exists());
+        assert_eq!(git(dir, &["rev-parse", "HEAD"], None).unwrap(), head);
+
+        // The state before the restore was recorded, so the restore can be undone.
+        assert_eq!(store.list().unwrap().len(), 3);
+        run_command(&store, "restore 3").unwrap();
+        assert!(dir.join("new.rs").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("lib.rs")).unwrap(),
+            "fn a() { 3 }\n"
+        );
+
+        assert!(run_command(&store, "restore 9").is_err());
+        assert_eq!(run_command(&store, "bogus").unwrap_err().to_string(), USAGE);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("/checkpoint"), Some(""));
+        assert_eq!(parse_command("/checkpoint restore 2"), Some(" restore 2"));
+        assert_eq!(parse_command("/checkpoints"), None);
+        assert_eq!(parse_command("/checkpointfoo list"), None);
+        assert_eq!(parse_command("explain /checkpoint"), None);
+    }
+
+    #[test]
+    fn test_open_outside_repository() {
+        let tmp_dir = Builder::new().prefix("test-checkpoint").tempdir().unwrap();
+        assert!(
+            CheckpointStore::open(tmp_dir.path(), "x")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -1,13 +1,73 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use alors::config::ConfigLayer;
 
 /// A command-line interface for the `alors` agent.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The prompt for the agent
     pub prompt: Option<String>,
 
+    /// Run the session in a temporary git worktree on a new branch.
+    /// At exit, the branch can be merged, kept or discarded.
+    #[arg(long)]
+    pub worktree: bool,
+
+    /// List the profiles defined in the config files and how they differ, then exit.
+    #[arg(long)]
+    pub list_profiles: bool,
+
+    /// Print the effective configuration with the source of each value, then exit.
+    #[arg(long)]
+    pub print_config: bool,
+
     #[command(flatten)]
     pub overrides: ConfigLayer,
 }
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage the configuration files.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Create the user config file, or add the missing default settings to it.
+    /// Existing values, comments and formatting are kept.
+    Init,
+    /// Show the settings and the file, profile or flag that set each one.
+    Show {
+        /// Show the complete effective configuration, including defaults.
+        #[arg(long)]
+        effective: bool,
+    },
+    /// Print the effective value of a key, e.g. `model` or `profiles.fast.model`.
+    Get { key: String },
+    /// Set a key in a config file, keeping its comments and formatting.
+    /// Lists can be given comma-separated.
+    Set {
+        key: String,
+        value: String,
+        /// Change the project config file instead of the user config file.
+        #[arg(long)]
+        project: bool,
+    },
+    /// Trust the project config file, so it may set keys that run commands or widen
+    /// permissions, like `auto_execute` or `check_command`. The trust ends when the file changes.
+    Trust,
+    /// Print the paths of the config files.
+    Path,
+    /// Open a config file in `$VISUAL` or `$EDITOR` and check it afterwards.
+    Edit {
+        /// Edit the project config file instead of the user config file.
+        #[arg(long)]
+        project: bool,
+    },
+}
@@ -1,21 +1,450 @@
+//! # API Client
+//!
+//! A client for OpenAI-compatible chat completion APIs, used for all backends. Requests and
+//! responses use the types of `openrouter_api`, but the HTTP transport is our own, so that
+//! custom providers can send extra headers.
+
 use crate::config::Config;
-use anyhow::{Result, bail};
-use openrouter_api::{OpenRouterClient, Ready};
+use anyhow::{Result, anyhow, bail};
+use futures::Stream;
+use once_cell::sync::Lazy;
+use openrouter_api::types::chat::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse,
+};
+use regex::Regex;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use std::pin::Pin;
 use std::time::Duration;
+use url::Url;
+
+static ENV_VAR_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+/// A chat completion request with parameters that `ChatCompletionRequest` has no fields for,
+/// like `temperature`. They are added to the JSON body of the request.
+pub struct ApiRequest {
+    pub completion: ChatCompletionRequest,
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ApiRequest {
+    fn body(&self) -> Result<Vec<u8>> {
+        let mut body = serde_json::to_value(&self.completion)?;
+        if let serde_json::Value::Object(fields) = &mut body {
+            fields.extend(self.parameters.clone());
+        }
+        Ok(serde_json::to_vec(&body)?)
+    }
+}
+
+/// An error of a request that may succeed when sent again: a rate limit, a server error,
+/// a failed connection or an interrupted stream.
+#[derive(Debug)]
+pub struct TransientError {
+    pub message: String,
+    /// The delay requested by the server with a `Retry-After` header.
+    pub retry_after: Option<Duration>,
+}
+
+impl std::fmt::Display for TransientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+impl TransientError {
+    fn new(message: String) -> Self {
+        Self {
+            message,
+            retry_after: None,
+        }
+    }
+}
+
+/// Whether a response status means that the same request may succeed later.
+fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+/// A stream of chunks of a streamed chat completion.
+pub type ChunkStream = Pin<Box<dyn Stream<Item = Result<ChatCompletionChunk>> + Send>>;
+
+pub struct ChatClient {
+    http: reqwest::Client,
+    completions_url: Url,
+    headers: HeaderMap,
+}
+
+pub fn initialize_client(config: &Config) -> Result<ChatClient> {
+    let backend_config = config.backend_config()?;
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(env_var) = &backend_config.api_key_env_var {
+        let api_key = std::env::var(env_var)
+            .map_err(|_| anyhow!("environment variable {env_var} not set"))?;
+        headers.insert(AUTHORIZATION, format!("Bearer {api_key}").parse()?);
+    }
+    for (name, value) in &backend_config.headers {
+        let value = expand_env_vars(value)?;
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| anyhow!("Invalid header name '{name}': {e}"))?,
+            HeaderValue::from_str(&value)
+                .map_err(|e| anyhow!("Invalid value for header '{name}': {e}"))?,
+        );
+    }
+
+    // Without a trailing slash, `join` would replace the last path segment.
+    let mut base_url = config.base_url.clone();
+    if !base_url.ends_with('/') {
+        base_url.push('/');
+    }
+    let completions_url = Url::parse(&base_url)
+        .and_then(|url| url.join("chat/completions"))
+        .map_err(|e| anyhow!("Invalid base URL '{}': {e}", config.base_url))?;
+
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .user_agent(concat!("alors/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    Ok(ChatClient {
+        http,
+        completions_url,
+        headers,
+    })
+}
+
+/// Returns the names of the variables referenced as `${VAR}` in a header value.
+pub fn referenced_env_vars(value: &str) -> impl Iterator<Item = String> + '_ {
+    ENV_VAR_RE
+        .captures_iter(value)
+        .map(|captures| captures[1].to_string())
+}
+
+/// Replaces `${VAR}` with the value of the environment variable `VAR`, so secrets in
+/// headers don't have to be written into config files.
+fn expand_env_vars(value: &str) -> Result<String> {
+    let mut missing = None;
+    let expanded = ENV_VAR_RE.replace_all(value, |captures: &regex::Captures| {
+        std::env::var(&captures[1]).unwrap_or_else(|_| {
+            missing = Some(captures[1].to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(var) => bail!("environment variable {var} not set"),
+        None => Ok(expanded.to_string()),
+    }
+}
 
-pub fn initialize_client(config: &Config) -> Result<OpenRouterClient<Ready>> {
-    let api_key = if let Some(env_var) = config.backend.config().api_key_env_var {
-        match std::env::var(env_var) {
-            Ok(val) => val,
-            Err(_) => bail!("environment variable {} not set", env_var),
+impl ChatClient {
+    pub async fn chat_completion(&self, request: &ApiRequest) -> Result<ChatCompletionResponse> {
+        let response = self.send(request).await?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| TransientError::new(format!("API response was interrupted: {e}")))?;
+        serde_json::from_slice(&body).map_err(|e| {
+            anyhow!(
+                "Invalid API response: {e}\n{}",
+                String::from_utf8_lossy(&body)
+            )
+        })
+    }
+
+    /// Sends a streaming request and returns the chunks of the server-sent events.
+    pub async fn chat_completion_stream(&self, request: &ApiRequest) -> Result<ChunkStream> {
+        let response = self.send(request).await?;
+        let state = EventReader {
+            response,
+            buffer: Vec::new(),
+            finished: false,
+        };
+        Ok(Box::pin(futures::stream::try_unfold(
+            state,
+            |mut state| async move { Ok(state.next_chunk().await?.map(|chunk| (chunk, state))) },
+        )))
+    }
+
+    async fn send(&self, request: &ApiRequest) -> Result<reqwest::Response> {
+        let response = self
+            .http
+            .post(self.completions_url.clone())
+            .headers(self.headers.clone())
+            .body(request.body()?)
+            .send()
+            .await
+            .map_err(|e| {
+                TransientError::new(format!(
+                    "API request to {} failed: {e}",
+                    self.completions_url
+                ))
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+                .map(Duration::from_secs);
+            let body = response.text().await.unwrap_or_default();
+            let message = format!("API request failed with status {status}: {}", body.trim());
+            if is_transient_status(status) {
+                return Err(TransientError {
+                    message,
+                    retry_after,
+                }
+                .into());
+            }
+            bail!(message);
+        }
+        Ok(response)
+    }
+}
+
+/// Reads chat completion chunks from a response with server-sent events.
+struct EventReader {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl EventReader {
+    /// Returns the next chunk, or `None` at `data: [DONE]` or the end of the response.
+    async fn next_chunk(&mut self) -> Result<Option<ChatCompletionChunk>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                // Other fields and comments, like OpenRouter's keep-alive, are skipped.
+                let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    return Ok(None);
+                }
+                let event: serde_json::Value = serde_json::from_str(data)
+                    .map_err(|e| anyhow!("Invalid event in API stream: {e}\n{data}"))?;
+                // Providers report failures after the response has started as error events.
+                if let Some(error) = event.get("error") {
+                    return Err(TransientError::new(format!("API stream failed: {error}")).into());
+                }
+                return Ok(Some(serde_json::from_value(event)?));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            let chunk = self
+                .response
+                .chunk()
+                .await
+                .map_err(|e| TransientError::new(format!("API stream was interrupted: {e}")))?;
+            match chunk {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => {
+                    // Handle a last line without a trailing newline.
+                    self.finished = true;
+                    self.buffer.push(b'\n');
+                }
+            }
+        }
+    }
+}
+
+/// A local HTTP server for tests, which answers requests with prepared raw responses.
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Reads an HTTP request with its body.
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 8192];
+        loop {
+            let n = stream.read(&mut buffer).await.unwrap_or_default();
+            request.extend_from_slice(&buffer[..n]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|l| l.trim().parse().unwrap_or(0))
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length || n == 0 {
+                    return text;
+                }
+            } else if n == 0 {
+                return text;
+            }
+        }
+    }
+
+    /// Builds a raw HTTP response, e.g. `response("200 OK", "application/json", "{}")`.
+    pub fn response(status: &str, content_type: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Answers the requests with `responses` in order, repeating the last one, and records
+    /// the requests. Returns the base URL and the request log.
+    pub async fn serve(responses: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let request_log = Arc::new(Mutex::new(Vec::new()));
+        let log = request_log.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let request = read_request(&mut stream).await;
+                let index = log.lock().unwrap().len().min(responses.len() - 1);
+                log.lock().unwrap().push(request);
+                let _ = stream.write_all(responses[index].as_bytes()).await;
+            }
+        });
+        (format!("http://{address}/v1"), request_log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_server::{response, serve};
+    use super::*;
+    use crate::backend::{Backend, ModelConfig, ProviderConfig};
+    use futures::StreamExt;
+
+    fn provider_config(base_url: &str) -> Config {
+        let mut config = Config::default();
+        config.providers.insert(
+            "gateway".to_string(),
+            ProviderConfig {
+                base_url: base_url.to_string(),
+                api_key_env_var: None,
+                headers: [("X-Team".to_string(), "core".to_string())].into(),
+                models: [("fast".to_string(), "meta/llama-3.1-8b".to_string())].into(),
+            },
+        );
+        config.models.insert(
+            "fast".to_string(),
+            ModelConfig {
+                temperature: Some(0.5),
+                ..Default::default()
+            },
+        );
+        config.merge(&crate::config::ConfigLayer {
+            backend: Some(Backend::Custom("gateway".to_string())),
+            model: Some("fast".to_string()),
+            ..Default::default()
+        });
+        config
+    }
+
+    fn request(config: &Config) -> ApiRequest {
+        ApiRequest {
+            completion: ChatCompletionRequest {
+                model: config.model_id(),
+                messages: vec![],
+                tools: None,
+                stream: Some(true),
+                response_format: None,
+                provider: None,
+                models: None,
+                transforms: None,
+            },
+            parameters: config.model_parameters(),
         }
-    } else {
-        // TODO: only call .with_api_key if let Some(config.backend.api_key_env_var())
-        "sk-or-v1-0000000000000000000000000000000000000000000000000000000000000000".to_string()
-    };
-    let client = OpenRouterClient::new()
-        .with_base_url(&config.base_url)?
-        .with_timeout(Duration::from_secs(config.timeout_seconds))
-        .with_api_key(api_key)?;
-    Ok(client)
+    }
+
+    #[tokio::test]
+    async fn test_stream_from_custom_provider() {
+        let chunk = |content: &str| {
+            format!(
+                r#"{{"id":"1","object":"chat.completion.chunk","created":0,"model":"m","choices":[{{"index":0,"delta":{{"role":"assistant","content":"{content}"}},"finish_reason":null}}]}}"#
+            )
+        };
+        let body = format!(
+            ": OPENROUTER PROCESSING\n\ndata: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hel"),
+            chunk("lo")
+        );
+        let (base_url, request_log) =
+            serve(vec![response("200 OK", "text/event-stream", &body)]).await;
+        let config = provider_config(&base_url);
+        assert_eq!(config.base_url, base_url);
+
+        let client = initialize_client(&config).unwrap();
+        let stream = client
+            .chat_completion_stream(&request(&config))
+            .await
+            .unwrap();
+        let contents: Vec<String> = stream
+            .map(|chunk| chunk.unwrap().choices[0].delta.content.clone().unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(contents, vec!["Hel", "lo"]);
+        let request = request_log.lock().unwrap()[0].clone();
+        assert!(request.starts_with("POST /v1/chat/completions HTTP/1.1"));
+        assert!(request.to_lowercase().contains("x-team: core"));
+        assert!(!request.to_lowercase().contains("authorization"));
+        assert!(request.contains(r#""model":"meta/llama-3.1-8b""#));
+        assert!(request.contains(r#""temperature":0.5"#));
+    }
+
+    #[tokio::test]
+    async fn test_error_status_is_reported() {
+        let body = r#"{"error":{"message":"Rate limited"}}"#;
+        let (base_url, _) = serve(vec![response(
+            "429 Too Many Requests",
+            "application/json",
+            body,
+        )])
+        .await;
+        let config = provider_config(&format!("{base_url}/"));
+
+        let client = initialize_client(&config).unwrap();
+        let error = client
+            .chat_completion(&request(&config))
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            r#"API request failed with status 429 Too Many Requests: {"error":{"message":"Rate limited"}}"#
+        );
+        assert!(error.downcast_ref::<TransientError>().is_some());
+    }
+
+    #[test]
+    fn test_unknown_provider() {
+        let mut config = Config::default();
+        config.merge(&crate::config::ConfigLayer {
+            backend: Some("vllm".parse().unwrap()),
+            ..Default::default()
+        });
+
+        let error = initialize_client(&config).err().unwrap();
+
+        assert!(error.to_string().starts_with("Unknown backend 'vllm'."));
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        assert_eq!(
+            expand_env_vars("Bearer ${PATH}").unwrap(),
+            format!("Bearer {}", std::env::var("PATH").unwrap())
+        );
+        assert_eq!(expand_env_vars("plain").unwrap(), "plain");
+        assert!(expand_env_vars("${ALORS_TEST_UNSET_VARIABLE}").is_err());
+    }
 }
@@ -0,0 +1,399 @@
+//! # Diagnostics
+//!
+//! This module parses compiler and linter output into structured diagnostics and renders
+//! them for the LLM, grouped by file, together with the affected lines in LIF form.
+//!
+//! ### Reasoning
+//! Plain `file:line:col` locations force the LLM to re-read files before it can edit them.
+//! By attaching the current LIDs of the affected lines, a diagnostic can be fixed directly
+//! with `edit_files`.
+
+use crate::config::Config;
+use crate::file_state::RangeSpec;
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::tools::read_files::merge_ranges;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+/// The number of lines shown before and after each diagnostic location.
+const CONTEXT_LINES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn parse(level: &str) -> Self {
+        if level.starts_with("error") {
+            Severity::Error
+        } else if level.starts_with("warning") {
+            Severity::Warning
+        } else {
+            Severity::Note
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A single diagnostic pointing at a location in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The file path as reported by the tool, usually relative to its working directory.
+    pub file: String,
+    /// The 1-indexed line of the location.
+    pub line: usize,
+    /// The 1-indexed, inclusive last line of the location.
+    pub end_line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    /// An error code like `E0425` or a lint name, if available.
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn headline(&self) -> String {
+        match &self.code {
+            Some(code) => format!("{}[{code}]", self.severity),
+            None => self.severity.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    level: String,
+    code: Option<RustcCode>,
+    spans: Vec<RustcSpan>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    is_primary: bool,
+    label: Option<String>,
+}
+
+/// Parses the output of cargo's `--message-format=json`.
+/// Lines that are not JSON compiler messages are ignored.
+pub fn parse_cargo_json(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for line in output.lines().filter(|l| l.starts_with('{')) {
+        let Ok(cargo_message) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+        if cargo_message.reason != "compiler-message" {
+            continue;
+        }
+        let Some(message) = cargo_message.message else {
+            continue;
+        };
+        // Summary messages like "aborting due to 2 previous errors" have no spans.
+        let Some(span) = message.spans.iter().find(|s| s.is_primary) else {
+            continue;
+        };
+        let text = match &span.label {
+            Some(label) if !label.is_empty() => format!("{} ({label})", message.message),
+            _ => message.message.clone(),
+        };
+        diagnostics.push(Diagnostic {
+            file: span.file_name.clone(),
+            line: span.line_start,
+            end_line: span.line_end.max(span.line_start),
+            column: span.column_start,
+            severity: Severity::parse(&message.level),
+            code: message.code.map(|c| c.code),
+            message: text,
+        });
+    }
+    dedup(diagnostics)
+}
+
+/// Matches `path:line:col: [severity:] message`, as printed by gcc, clang, tsc, eslint (unix), etc.
+static LOCATION_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?P<file>[^\s:][^:]*):(?P<line>\d+):(?P<col>\d+):?\s*(?:-\s*)?(?:(?P<severity>error|warning|note|info)\b[^:]*:\s*)?(?P<message>.*)$",
+    )
+    .unwrap()
+});
+
+/// Matches the `--> path:line:col` location line of rustc's human-readable output.
+static ARROW_LOCATION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*--> (?P<file>.+):(?P<line>\d+):(?P<col>\d+)$").unwrap());
+
+/// Matches rustc's `error[E0425]: message` header lines.
+static RUSTC_HEADER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?P<severity>error|warning)(?:\[(?P<code>[^\]]+)\])?: (?P<message>.+)$").unwrap()
+});
+
+/// Parses generic `file:line:col` diagnostics from human-readable output.
+/// rustc's multi-line format (a header followed by a `-->` location) is supported as well.
+pub fn parse_generic(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut pending_header: Option<(Severity, Option<String>, String)> = None;
+
+    for line in output.lines() {
+        if let Some(caps) = RUSTC_HEADER_RE.captures(line) {
+            pending_header = Some((
+                Severity::parse(&caps["severity"]),
+                caps.name("code").map(|c| c.as_str().to_string()),
+                caps["message"].to_string(),
+            ));
+            continue;
+        }
+
+        if let Some(caps) = ARROW_LOCATION_RE.captures(line) {
+            if let Some((severity, code, message)) = pending_header.take() {
+                let line_number: usize = caps["line"].parse().unwrap_or(1);
+                diagnostics.push(Diagnostic {
+                    file: caps["file"].to_string(),
+                    line: line_number,
+                    end_line: line_number,
+                    column: caps["col"].parse().unwrap_or(1),
+                    severity,
+                    code,
+                    message,
+                });
+            }
+            continue;
+        }
+
+        if let Some(caps) = LOCATION_LINE_RE.captures(line) {
+            let line_number: usize = caps["line"].parse().unwrap_or(1);
+            diagnostics.push(Diagnostic {
+                file: caps["file"].to_string(),
+                line: line_number,
+                end_line: line_number,
+                column: caps["col"].parse().unwrap_or(1),
+                severity: caps
+                    .name("severity")
+                    .map_or(Severity::Error, |s| Severity::parse(s.as_str())),
+                code: None,
+                message: caps["message"].trim().to_string(),
+            });
+        }
+    }
+    dedup(diagnostics)
+}
+
+/// Removes duplicates, e.g. when cargo reports the same warning for a lib and its test target.
+fn dedup(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let mut seen = HashSet::new();
+    diagnostics
+        .into_iter()
+        .filter(|d| seen.insert((d.file.clone(), d.line, d.column, d.message.clone())))
+        .collect()
+}
+
+/// Summarizes the number of diagnostics per severity, e.g. "2 errors, 1 warning".
+pub fn summarize<'a>(diagnostics: impl IntoIterator<Item = &'a Diagnostic>) -> String {
+    let (mut errors, mut warnings, mut notes) = (0, 0, 0);
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Error => errors += 1,
+            Severity::Warning => warnings += 1,
+            Severity::Note => notes += 1,
+        }
+    }
+    let plural = |n: usize, word: &str| {
+        if n == 1 {
+            format!("{n} {word}")
+        } else {
+            format!("{n} {word}s")
+        }
+    };
+    let mut parts = vec![plural(errors, "error"), plural(warnings, "warning")];
+    if notes > 0 {
+        parts.push(plural(notes, "note"));
+    }
+    parts.join(", ")
+}
+
+/// Renders diagnostics grouped by file, in order of first appearance.
+///
+/// Relative paths are resolved against `base_dir`. For every accessible file, the
+/// diagnostics are annotated with the LID of their line, followed by the affected
+/// lines (with some context) in LIF form.
+pub fn render_diagnostics(
+    diagnostics: &[Diagnostic],
+    base_dir: &Path,
+    config: &Config,
+    fsm: &mut FileStateManager,
+) -> String {
+    let mut files: Vec<(&str, Vec<&Diagnostic>)> = Vec::new();
+    for diagnostic in diagnostics {
+        match files.iter_mut().find(|(file, _)| *file == diagnostic.file) {
+            Some((_, group)) => group.push(diagnostic),
+            None => files.push((&diagnostic.file, vec![diagnostic])),
+        }
+    }
+
+    let mut sections = Vec::new();
+    for (file, group) in files {
+        let path = base_dir.join(file);
+        let path_str = path.to_string_lossy();
+        let file_state = if permissions::is_path_accessible(&path, &config.accessible_paths).is_ok()
+        {
+            fsm.open_file(&path_str).ok()
+        } else {
+            None
+        };
+
+        let mut section = vec![format!("{file}: {}", summarize(group.iter().copied()))];
+        for diagnostic in &group {
+            let lid = file_state
+                .as_ref()
+                .and_then(|state| state.lid_at_line(diagnostic.line))
+                .map(|lid| format!(" {lid}"))
+                .unwrap_or_default();
+            section.push(format!(
+                "  {} {}:{}{lid}: {}",
+                diagnostic.headline(),
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.message
+            ));
+        }
+
+        match file_state {
+            Some(state) => {
+                let ranges = merge_ranges(
+                    group
+                        .iter()
+                        .map(|d| RangeSpec {
+                            start_line: d.line.saturating_sub(CONTEXT_LINES).max(1),
+                            end_line: d.end_line + CONTEXT_LINES,
+                        })
+                        .collect(),
+                );
+                section.push(state.display_lif_contents_for_ranges(Some(&ranges)));
+            }
+            None => section.push("[File not accessible, no LIDs available]".to_string()),
+        }
+        sections.push(section.join("\n"));
+    }
+    sections.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    const CARGO_OUTPUT: &str = r#"{"reason":"compiler-artifact","package_id":"foo","target":{}}
+{"reason":"compiler-message","message":{"message":"cannot find value `x` in this scope","level":"error","code":{"code":"E0425","explanation":null},"spans":[{"file_name":"src/main.rs","line_start":3,"line_end":3,"column_start":13,"column_end":14,"is_primary":true,"label":"not found in this scope"}],"children":[],"rendered":"error[E0425]: ..."}}
+{"reason":"compiler-message","message":{"message":"unused variable: `y`","level":"warning","code":{"code":"unused_variables","explanation":null},"spans":[{"file_name":"src/main.rs","line_start":2,"line_end":2,"column_start":9,"column_end":10,"is_primary":true,"label":null}],"children":[],"rendered":"warning: ..."}}
+{"reason":"compiler-message","message":{"message":"aborting due to 1 previous error","level":"error","code":null,"spans":[],"children":[],"rendered":"error: aborting"}}
+{"reason":"build-finished","success":false}"#;
+
+    #[test]
+    fn test_parse_cargo_json() {
+        let diagnostics = parse_cargo_json(CARGO_OUTPUT);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].file, "src/main.rs");
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].column, 13);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("E0425"));
+        assert_eq!(
+            diagnostics[0].message,
+            "cannot find value `x` in this scope (not found in this scope)"
+        );
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(summarize(&diagnostics), "1 error, 1 warning");
+    }
+
+    #[test]
+    fn test_parse_generic() {
+        let output = "\
+src/app.ts:10:5 - error TS2304: Cannot find name 'foo'.
+main.c:4:1: warning: implicit declaration of function 'bar'
+error[E0308]: mismatched types
+ --> src/lib.rs:7:9
+  |
+some unrelated line";
+        let diagnostics = parse_generic(output);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].file, "src/app.ts");
+        assert_eq!(diagnostics[0].line, 10);
+        assert_eq!(diagnostics[0].message, "Cannot find name 'foo'.");
+        assert_eq!(diagnostics[1].file, "main.c");
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(
+            diagnostics[1].message,
+            "implicit declaration of function 'bar'"
+        );
+        assert_eq!(diagnostics[2].file, "src/lib.rs");
+        assert_eq!(diagnostics[2].line, 7);
+        assert_eq!(diagnostics[2].code.as_deref(), Some("E0308"));
+        assert_eq!(diagnostics[2].message, "mismatched types");
+    }
+
+    #[test]
+    fn test_render_diagnostics_with_lids() {
+        let tmp_dir = Builder::new()
+            .prefix("test-diagnostics-")
+            .tempdir()
+            .unwrap();
+        let content = (1..=10)
+            .map(|i| format!("line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(tmp_dir.path().join("code.txt"), content).unwrap();
+        let config = Config {
+            accessible_paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let diagnostics = parse_generic("code.txt:5:1: error: bad line\nother.txt:1:1: oops");
+        let mut fsm = FileStateManager::new();
+
+        let output = render_diagnostics(&diagnostics, tmp_dir.path(), &config, &mut fsm);
+
+        let file_path = tmp_dir.path().join("code.txt");
+        let state = fsm.open_file(file_path.to_str().unwrap()).unwrap();
+        let lid = state.lid_at_line(5).unwrap();
+        assert!(output.contains(&format!(
+            "code.txt: 1 error, 0 warnings\n  error 5:1 {lid}: bad line"
+        )));
+        assert!(output.contains("| Lines: 3-7/10"));
+        assert!(output.contains(&format!("5    {lid}: line 5")));
+        assert!(
+            output.contains(
+                "other.txt: 1 error, 0 warnings\n  error 1:1: oops\n[File not accessible"
+            )
+        );
+    }
+}
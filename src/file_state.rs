@@ -22,7 +22,7 @@
 //! -   **`lif_hash`**: A SHA-1 hash of the file's LIF representation, acting as a version identifier.
 
 use crate::diff;
-use crate::patch::PatchOperation;
+use crate::patch::{InsertOp, PatchOperation, ReplaceOp};
 use anyhow::{Result, anyhow};
 use fractional_index::FractionalIndex;
 use rand::Rng;
@@ -211,6 +211,57 @@ impl FileState {
         Ok(diff)
     }
 
+    /// Computes the patch operations that turn this state into `content`.
+    ///
+    /// ### Reasoning
+    /// This is used to reflect changes made outside the LIF protocol, like a formatter run,
+    /// back into the state. Lines that are unchanged keep their LIDs, so anchors the LLM has
+    /// already seen stay valid. Only changed lines get new LIDs.
+    pub fn patch_to_content(&self, content: &str) -> Vec<PatchOperation> {
+        let keys: Vec<&FractionalIndex> = self.lines.keys().collect();
+        let old_lines: Vec<&str> = self.lines.values().map(|(line, _)| line.as_str()).collect();
+        let new_lines: Vec<&str> = content.lines().collect();
+        let with_suffixes = |lines: &[&str]| -> Vec<(String, String)> {
+            lines
+                .iter()
+                .map(|line| (line.to_string(), generate_random_suffix()))
+                .collect()
+        };
+
+        let mut patch = Vec::new();
+        for op in similar::capture_diff_slices(similar::Algorithm::Myers, &old_lines, &new_lines) {
+            match op {
+                similar::DiffOp::Equal { .. } => {}
+                similar::DiffOp::Delete {
+                    old_index, old_len, ..
+                } => patch.push(PatchOperation::Replace(ReplaceOp {
+                    start_lid: keys[old_index].clone(),
+                    end_lid: keys[old_index + old_len - 1].clone(),
+                    content: Vec::new(),
+                })),
+                similar::DiffOp::Insert {
+                    old_index,
+                    new_index,
+                    new_len,
+                } => patch.push(PatchOperation::Insert(InsertOp {
+                    after_lid: old_index.checked_sub(1).map(|i| keys[i].clone()),
+                    content: with_suffixes(&new_lines[new_index..new_index + new_len]),
+                })),
+                similar::DiffOp::Replace {
+                    old_index,
+                    old_len,
+                    new_index,
+                    new_len,
+                } => patch.push(PatchOperation::Replace(ReplaceOp {
+                    start_lid: keys[old_index].clone(),
+                    end_lid: keys[old_index + old_len - 1].clone(),
+                    content: with_suffixes(&new_lines[new_index..new_index + new_len]),
+                })),
+            }
+        }
+        patch
+    }
+
     /// Calculates a diff for a patch without applying it to the current state.
     pub fn calculate_patch_diff(&self, patch: &[PatchOperation]) -> Result<String> {
         let mut temp_state = self.clone();
@@ -394,6 +445,28 @@ impl FileState {
         content
     }
 
+    /// Returns the LID of the line with the given 1-indexed line number, if it exists.
+    pub fn lid_at_line(&self, line_number: usize) -> Option<String> {
+        self.lines
+            .iter()
+            .nth(line_number.checked_sub(1)?)
+            .map(|(index, (_, suffix))| Self::display_lid(index, suffix))
+    }
+
+    /// Returns the 1-indexed line number of a LID.
+    /// Fails if the LID does not exist or its suffix does not match, i.e. the line was changed.
+    pub fn line_of_lid(&self, lid_str: &str) -> Result<usize> {
+        let (index, suffix) = Self::parse_lid(lid_str)?;
+        match self.lines.get(&index) {
+            Some((_, actual_suffix)) if *actual_suffix == suffix => {
+                Ok(self.lines.range(..&index).count() + 1)
+            }
+            _ => Err(anyhow!(
+                "LID '{lid_str}' not found in file. Please re-read the file to get the latest LIDs."
+            )),
+        }
+    }
+
     /// Formats an index and suffix into the `lid-index_suffix` string format.
     pub fn display_lid(index: &FractionalIndex, suffix: &str) -> String {
         format!("lid-{}_{}", index.to_string(), suffix)
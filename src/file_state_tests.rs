@@ -258,3 +258,44 @@ fn test_calculate_patch_diff_does_not_mutate() {
     let disk_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(disk_content, content, "File on disk should not be modified");
 }
+
+#[test]
+fn test_lid_at_line() {
+    let (_tmp_dir, file_path) = setup_test_file("line 1\nline 2");
+    let state = FileState::new(file_path, "line 1\nline 2");
+    let (index, (_, suffix)) = state.lines.iter().nth(1).unwrap();
+
+    assert_eq!(
+        state.lid_at_line(2),
+        Some(FileState::display_lid(index, suffix))
+    );
+    assert_eq!(state.lid_at_line(0), None);
+    assert_eq!(state.lid_at_line(3), None);
+}
+
+#[test]
+fn test_patch_to_content_keeps_unchanged_lids() {
+    let (_tmp_dir, file_path) = setup_test_file("a\nb\nc\nd\n");
+    let mut state = FileState::new(file_path, "a\nb\nc\nd\n");
+    let old_lids: Vec<String> = (1..=4).map(|n| state.lid_at_line(n).unwrap()).collect();
+
+    let patch = state.patch_to_content("new\na\nB\nd\ne\n");
+    state.apply_patch(&patch).unwrap();
+
+    assert_eq!(state.get_full_content(), "new\na\nB\nd\ne\n");
+    assert_eq!(state.lid_at_line(2).unwrap(), old_lids[0]);
+    assert_eq!(state.lid_at_line(4).unwrap(), old_lids[3]);
+    assert!(!old_lids.contains(&state.lid_at_line(3).unwrap()));
+}
+
+#[test]
+fn test_line_of_lid() {
+    let (_tmp_dir, file_path) = setup_test_file("a\nb\nc");
+    let state = FileState::new(file_path, "a\nb\nc");
+    let lid = state.lid_at_line(2).unwrap();
+
+    assert_eq!(state.line_of_lid(&lid).unwrap(), 2);
+    let (index, _) = FileState::parse_lid(&lid).unwrap();
+    let stale_lid = FileState::display_lid(&index, "stale");
+    assert!(state.line_of_lid(&stale_lid).is_err());
+}
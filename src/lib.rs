@@ -1,18 +1,25 @@
 pub mod agent;
 pub mod backend;
+pub mod checkpoint;
 pub mod client;
 pub mod config;
+pub mod diagnostics;
 pub mod diff;
 pub mod enricher;
 pub mod file_state;
 pub mod file_state_manager;
+pub mod lsp;
 pub mod patch;
 pub mod path_expander;
 pub mod permissions;
+pub mod process_manager;
 pub mod prompt_builder;
 pub mod streaming_executor;
+pub mod syntax;
+pub mod system_prompt;
 pub mod tool_collection;
 pub mod tools;
+pub mod worktree;
 
 pub use config::Config;
 pub use tool_collection::ToolCollection;
@@ -0,0 +1,733 @@
+//! # Language Server Client
+//!
+//! A minimal client for the Language Server Protocol (LSP) over stdio. It gives the agent a
+//! semantic view of the code: diagnostics, go-to-definition, references and hover.
+//!
+//! ## Design
+//!
+//! -   **`LspClient`**: One running server. Messages are read by a background thread, which
+//!     routes responses to the waiting request and stores published diagnostics.
+//! -   **`LspManager`**: Starts one client per configured command on first use. It is shared
+//!     between the `language_server` and `edit_files` tools and the `Agent`, which stops all
+//!     servers on exit.
+//! -   **Document sync**: Documents are opened lazily when a request needs them. Afterwards,
+//!     every edit written by `edit_files` is sent to the manager as `didChange`.
+//!
+//! ### Reasoning
+//! The client uses blocking IO instead of async, because edits are synced from the
+//! synchronous edit path. Tools call into it via `spawn_blocking`.
+
+use crate::config::{self, Config};
+use crate::permissions;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+/// How long to wait for the response to a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for the response to `shutdown` before the server is killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long to wait for the server to publish diagnostics after a document changed.
+pub const DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// The 0-indexed line.
+    pub line: u32,
+    /// The 0-indexed column, in UTF-16 code units.
+    pub character: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LspDiagnostic {
+    pub range: Range,
+    /// 1 = error, 2 = warning, 3 = information, 4 = hint.
+    #[serde(default)]
+    pub severity: Option<u8>,
+    #[serde(default)]
+    pub code: Option<Value>,
+    #[serde(default)]
+    pub source: Option<String>,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LocationLink {
+    target_uri: String,
+    target_selection_range: Range,
+}
+
+#[derive(Default)]
+struct PublishedDiagnostics {
+    /// Incremented on every `publishDiagnostics` notification.
+    generation: u64,
+    /// The latest diagnostics per URI, with the generation they were published in.
+    by_uri: HashMap<String, (u64, Vec<LspDiagnostic>)>,
+}
+
+/// State shared between the client and its reader thread.
+struct Shared {
+    writer: Mutex<Box<dyn Write + Send>>,
+    pending: Mutex<HashMap<i64, mpsc::Sender<Result<Value>>>>,
+    diagnostics: Mutex<PublishedDiagnostics>,
+    diagnostics_changed: Condvar,
+}
+
+impl Shared {
+    fn send(&self, message: &Value) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        write_message(&mut *writer, message)
+    }
+
+    fn dispatch(&self, message: Value) {
+        let method = message.get("method").and_then(Value::as_str);
+        let id = message.get("id").cloned();
+        match (method, id) {
+            // A response to one of our requests.
+            (None, Some(id)) => {
+                let Some(sender) = id
+                    .as_i64()
+                    .and_then(|id| self.pending.lock().unwrap().remove(&id))
+                else {
+                    return;
+                };
+                let result = match message.get("error") {
+                    Some(error) => Err(anyhow!(
+                        "The language server returned an error: {}",
+                        error
+                            .get("message")
+                            .and_then(Value::as_str)
+                            .unwrap_or("unknown error")
+                    )),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                };
+                let _ = sender.send(result);
+            }
+            // A request from the server. We don't support any, but must answer so it doesn't hang.
+            (Some(method), Some(id)) => {
+                let result = if method == "workspace/configuration" {
+                    let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                    Value::Array(vec![Value::Null; items])
+                } else {
+                    Value::Null
+                };
+                let _ = self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+            }
+            (Some("textDocument/publishDiagnostics"), None) => {
+                let params = &message["params"];
+                let Some(uri) = params["uri"].as_str() else {
+                    return;
+                };
+                let diagnostics: Vec<LspDiagnostic> =
+                    serde_json::from_value(params["diagnostics"].clone()).unwrap_or_default();
+                let mut published = self.diagnostics.lock().unwrap();
+                published.generation += 1;
+                let generation = published.generation;
+                published
+                    .by_uri
+                    .insert(uri.to_string(), (generation, diagnostics));
+                self.diagnostics_changed.notify_all();
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Document {
+    version: i64,
+    content: String,
+    /// The diagnostics generation at the time of the last change.
+    changed_at: u64,
+}
+
+/// A connection to one running language server.
+pub struct LspClient {
+    shared: Arc<Shared>,
+    next_id: AtomicI64,
+    documents: Mutex<HashMap<PathBuf, Document>>,
+    child: Mutex<Option<Child>>,
+}
+
+impl LspClient {
+    /// Starts a language server with `sh -c command` in `root` and initializes it.
+    pub fn spawn(command: &str, root: &Path, env: &[(OsString, OsString)]) -> Result<Arc<Self>> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(root)
+            .env_clear()
+            .envs(env.iter().cloned())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start language server `{command}`: {e}"))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Self::connect(stdout, stdin, Some(child), root)
+            .map_err(|e| anyhow!("Failed to initialize language server `{command}`: {e}"))
+    }
+
+    /// Connects to a language server over an arbitrary transport and initializes it.
+    pub fn connect(
+        reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+        child: Option<Child>,
+        root: &Path,
+    ) -> Result<Arc<Self>> {
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(Box::new(writer)),
+            pending: Mutex::new(HashMap::new()),
+            diagnostics: Mutex::new(PublishedDiagnostics::default()),
+            diagnostics_changed: Condvar::new(),
+        });
+
+        let reader_shared = shared.clone();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                reader_shared.dispatch(message);
+            }
+            // The server is gone. Dropping the senders fails all waiting requests.
+            reader_shared.pending.lock().unwrap().clear();
+        });
+
+        let client = Arc::new(Self {
+            shared,
+            next_id: AtomicI64::new(1),
+            documents: Mutex::new(HashMap::new()),
+            child: Mutex::new(child),
+        });
+
+        let root_uri = path_to_uri(root);
+        client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": "root" }],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": { "didSave": false },
+                        "publishDiagnostics": {},
+                        "definition": { "linkSupport": true },
+                        "references": {},
+                        "hover": { "contentFormat": ["plaintext", "markdown"] }
+                    }
+                }
+            }),
+        )?;
+        client.notify("initialized", json!({}))?;
+
+        Ok(client)
+    }
+
+    /// Sends a request and waits for its result.
+    pub fn request(&self, method: &str, params: Value) -> Result<Value> {
+        self.request_with_timeout(method, params, REQUEST_TIMEOUT)
+    }
+
+    fn request_with_timeout(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = mpsc::channel();
+        self.shared.pending.lock().unwrap().insert(id, sender);
+        self.shared.send(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.shared.pending.lock().unwrap().remove(&id);
+                Err(anyhow!(
+                    "The language server did not answer `{method}` within {}s.",
+                    timeout.as_secs()
+                ))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!(
+                "The language server exited before answering `{method}`."
+            )),
+        }
+    }
+
+    /// Sends a notification.
+    pub fn notify(&self, method: &str, params: Value) -> Result<()> {
+        self.shared.send(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    /// Makes sure the server has the current content of a document,
+    /// opening it with `didOpen` or updating it with `didChange`.
+    pub fn sync_document(&self, path: &Path, content: &str) -> Result<()> {
+        let mut documents = self.documents.lock().unwrap();
+        let generation = self.shared.diagnostics.lock().unwrap().generation;
+        match documents.get_mut(path) {
+            Some(document) => {
+                if document.content == content {
+                    return Ok(());
+                }
+                document.version += 1;
+                document.content = content.to_string();
+                document.changed_at = generation;
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": path_to_uri(path), "version": document.version },
+                        "contentChanges": [{ "text": content }]
+                    }),
+                )
+            }
+            None => {
+                documents.insert(
+                    path.to_path_buf(),
+                    Document {
+                        version: 1,
+                        content: content.to_string(),
+                        changed_at: generation,
+                    },
+                );
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {
+                            "uri": path_to_uri(path),
+                            "languageId": language_id(path),
+                            "version": 1,
+                            "text": content
+                        }
+                    }),
+                )
+            }
+        }
+    }
+
+    /// Syncs a changed file, but only if the server already has it open.
+    fn document_changed(&self, path: &Path, content: &str) -> Result<()> {
+        if !self.documents.lock().unwrap().contains_key(path) {
+            return Ok(());
+        }
+        self.sync_document(path, content)
+    }
+
+    /// Waits until the server publishes diagnostics for the current version of a document.
+    /// Returns the latest diagnostics after a timeout, or `None` if there never were any.
+    pub fn wait_for_diagnostics(
+        &self,
+        path: &Path,
+        timeout: Duration,
+    ) -> Option<Vec<LspDiagnostic>> {
+        let uri = path_to_uri(path);
+        let changed_at = self
+            .documents
+            .lock()
+            .unwrap()
+            .get(path)
+            .map_or(0, |document| document.changed_at);
+        let deadline = Instant::now() + timeout;
+
+        let mut published = self.shared.diagnostics.lock().unwrap();
+        loop {
+            if let Some((generation, diagnostics)) = published.by_uri.get(&uri)
+                && *generation > changed_at
+            {
+                return Some(diagnostics.clone());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return published
+                    .by_uri
+                    .get(&uri)
+                    .map(|(_, diagnostics)| diagnostics.clone());
+            }
+            published = self
+                .shared
+                .diagnostics_changed
+                .wait_timeout(published, remaining)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Asks the server to shut down, then kills it.
+    pub fn shutdown(&self) {
+        let _ = self.request_with_timeout("shutdown", Value::Null, SHUTDOWN_TIMEOUT);
+        let _ = self.notify("exit", Value::Null);
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Starts and holds the language servers configured in `language_servers`.
+pub struct LspManager {
+    root: PathBuf,
+    clients: HashMap<String, Arc<LspClient>>,
+}
+
+impl LspManager {
+    /// Creates a manager whose servers use `root` as the workspace root.
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Returns the client responsible for a file, starting its server if necessary.
+    pub fn client_for(&mut self, path: &Path, config: &Config) -> Result<Arc<LspClient>> {
+        let command = config::command_for_extension(&config.language_servers, path)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No language server is configured for '{}'. Add one to `language_servers`, e.g. `rs=rust-analyzer`.",
+                    path.display()
+                )
+            })?;
+        if let Some(client) = self.clients.get(command) {
+            return Ok(client.clone());
+        }
+        let client = LspClient::spawn(command, &self.root, &permissions::command_env(config))?;
+        self.clients.insert(command.to_string(), client.clone());
+        Ok(client)
+    }
+
+    /// Uses an already connected client for `command`, e.g. one with a custom transport.
+    pub fn attach(&mut self, command: &str, client: Arc<LspClient>) {
+        self.clients.insert(command.to_string(), client);
+    }
+
+    /// Sends the new content of a file to every running server that has it open.
+    pub fn notify_file_changed(&self, path: &Path, content: &str) {
+        for client in self.clients.values() {
+            // A failing server must not fail the edit, which is already written.
+            let _ = client.document_changed(path, content);
+        }
+    }
+
+    /// Shuts down all running servers.
+    pub fn stop_all(&mut self) {
+        for (_, client) in self.clients.drain() {
+            client.shutdown();
+        }
+    }
+}
+
+/// Parses the result of `textDocument/definition` or `textDocument/references`,
+/// which can be a single location, a list of locations or a list of location links.
+pub fn parse_locations(result: Value) -> Vec<Location> {
+    let items = match result {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        item => vec![item],
+    };
+    items
+        .into_iter()
+        .filter_map(|item| {
+            serde_json::from_value::<Location>(item.clone())
+                .ok()
+                .or_else(|| {
+                    serde_json::from_value::<LocationLink>(item)
+                        .ok()
+                        .map(|link| Location {
+                            uri: link.target_uri,
+                            range: link.target_selection_range,
+                        })
+                })
+        })
+        .collect()
+}
+
+/// Extracts the text of a `textDocument/hover` result.
+pub fn hover_text(result: &Value) -> Option<String> {
+    fn marked_string(value: &Value) -> Option<String> {
+        match value {
+            Value::String(text) => Some(text.clone()),
+            Value::Object(object) => object
+                .get("value")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            _ => None,
+        }
+    }
+    let text = match result.get("contents")? {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(marked_string)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        contents => marked_string(contents)?,
+    };
+    (!text.trim().is_empty()).then(|| text.trim().to_string())
+}
+
+/// Converts a file path to a `file://` URI.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// Converts a `file://` URI to a file path.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%'
+            && let Some(byte) = encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            bytes.push(byte);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Converts a byte offset in a line to an LSP column in UTF-16 code units.
+pub fn utf16_column(line: &str, byte_offset: usize) -> u32 {
+    line[..byte_offset.min(line.len())].encode_utf16().count() as u32
+}
+
+fn language_id(path: &Path) -> String {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    match extension {
+        "rs" => "rust",
+        "ts" => "typescript",
+        "tsx" => "typescriptreact",
+        "js" => "javascript",
+        "jsx" => "javascriptreact",
+        "py" => "python",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Writes a JSON-RPC message with its `Content-Length` header.
+fn write_message(writer: &mut dyn Write, message: &Value) -> Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a JSON-RPC message. Returns `None` at the end of the stream.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            content_length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let length = content_length.ok_or_else(|| anyhow!("Message without Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// A tiny in-process language server for tests.
+///
+/// It publishes one error on the first line of every opened or changed document, answers
+/// `definition` with the first line of the document, `references` with the first two lines,
+/// and `hover` with a fixed text. Received `didChange` notifications are forwarded to the
+/// returned receiver.
+#[cfg(test)]
+pub(crate) fn connect_fake_server(root: &Path) -> (Arc<LspClient>, mpsc::Receiver<Value>) {
+    let (client_reader, mut server_writer) = std::io::pipe().unwrap();
+    let (server_reader, client_writer) = std::io::pipe().unwrap();
+    let (changes_sender, changes) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(server_reader);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            let params = &message["params"];
+            let respond = |writer: &mut std::io::PipeWriter, result: Value| {
+                let _ = write_message(
+                    writer,
+                    &json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }),
+                );
+            };
+            let location = |line: u32| {
+                json!({
+                    "uri": params["textDocument"]["uri"],
+                    "range": {
+                        "start": { "line": line, "character": 3 },
+                        "end": { "line": line, "character": 7 }
+                    }
+                })
+            };
+            match message["method"].as_str().unwrap_or_default() {
+                "initialize" => respond(&mut server_writer, json!({ "capabilities": {} })),
+                "textDocument/didOpen" | "textDocument/didChange" => {
+                    if message["method"] == "textDocument/didChange" {
+                        let _ = changes_sender.send(params.clone());
+                    }
+                    let _ = write_message(
+                        &mut server_writer,
+                        &json!({
+                            "jsonrpc": "2.0",
+                            "method": "textDocument/publishDiagnostics",
+                            "params": {
+                                "uri": params["textDocument"]["uri"],
+                                "diagnostics": [{
+                                    "range": location(0)["range"],
+                                    "severity": 1,
+                                    "code": "E0001",
+                                    "message": "fake error"
+                                }]
+                            }
+                        }),
+                    );
+                }
+                "textDocument/definition" => respond(&mut server_writer, location(0)),
+                "textDocument/references" => {
+                    respond(&mut server_writer, json!([location(0), location(1)]))
+                }
+                "textDocument/hover" => respond(
+                    &mut server_writer,
+                    json!({ "contents": { "kind": "markdown", "value": "fn fake()" } }),
+                ),
+                "shutdown" => respond(&mut server_writer, Value::Null),
+                "exit" => break,
+                _ => {}
+            }
+        }
+    });
+
+    let client = LspClient::connect(client_reader, client_writer, None, root).unwrap();
+    (client, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_round_trip() {
+        let path = Path::new("/tmp/some dir/ä.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/some%20dir/%C3%A4.rs");
+        assert_eq!(uri_to_path(&uri).unwrap(), path);
+    }
+
+    #[test]
+    fn test_parse_locations_and_hover() {
+        let range = json!({
+            "start": { "line": 1, "character": 2 },
+            "end": { "line": 1, "character": 5 }
+        });
+        let link = json!([{
+            "targetUri": "file:///a.rs",
+            "targetRange": range,
+            "targetSelectionRange": range
+        }]);
+        assert_eq!(parse_locations(link)[0].uri, "file:///a.rs");
+        assert_eq!(
+            parse_locations(json!({ "uri": "file:///b.rs", "range": range }))[0]
+                .range
+                .start,
+            Position {
+                line: 1,
+                character: 2
+            }
+        );
+        assert!(parse_locations(Value::Null).is_empty());
+
+        let hover = json!({ "contents": [{ "language": "rust", "value": "fn f()" }, "Docs."] });
+        assert_eq!(hover_text(&hover).unwrap(), "fn f()\n\nDocs.");
+        assert_eq!(utf16_column("ä = x", "ä = ".len()), 4);
+    }
+
+    #[test]
+    fn test_fake_server_round_trip() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("test-lsp")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("main.rs");
+        let (client, changes) = connect_fake_server(tmp_dir.path());
+
+        client.sync_document(&path, "fn main() {}\n").unwrap();
+        let diagnostics = client
+            .wait_for_diagnostics(&path, DIAGNOSTICS_TIMEOUT)
+            .unwrap();
+        assert_eq!(diagnostics[0].message, "fake error");
+
+        let result = client
+            .request(
+                "textDocument/definition",
+                json!({
+                    "textDocument": { "uri": path_to_uri(&path) },
+                    "position": { "line": 0, "character": 3 }
+                }),
+            )
+            .unwrap();
+        assert_eq!(uri_to_path(&parse_locations(result)[0].uri).unwrap(), path);
+
+        // Edits of open documents are synced, other files are ignored.
+        let mut manager = LspManager::new(tmp_dir.path().to_path_buf());
+        manager.attach("fake-ls", client.clone());
+        manager.notify_file_changed(&tmp_dir.path().join("other.rs"), "");
+        manager.notify_file_changed(&path, "fn main() { 1 }\n");
+        let change = changes.recv_timeout(REQUEST_TIMEOUT).unwrap();
+        assert_eq!(change["textDocument"]["version"], 2);
+        assert_eq!(change["contentChanges"][0]["text"], "fn main() { 1 }\n");
+
+        client.shutdown();
+    }
+}
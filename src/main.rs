@@ -1,10 +1,19 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use clap::Parser;
 use console::style;
 use openrouter_api::types::chat::Message;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use alors::{agent::Agent, tool_collection::ToolCollection};
+use alors::{
+    agent::Agent,
+    checkpoint::CheckpointStore,
+    config::{self, ConfigLayer, ConfigSource},
+    lsp::LspManager,
+    process_manager::ProcessManager,
+    system_prompt::SystemPromptTemplate,
+    tool_collection::ToolCollection,
+    worktree::Worktree,
+};
 
 mod cli;
 mod ui;
@@ -12,19 +21,85 @@ mod ui;
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = cli::Cli::parse();
-    let config = alors::config::load(&cli.overrides)?;
+    if let Some(cli::Command::Config { command }) = &cli.command {
+        return run_config_command(command, &cli.overrides);
+    }
+
+    let loaded_config = config::load(&cli.overrides)?;
+    print_config_warnings(&loaded_config);
+    if cli.list_profiles {
+        println!("{}", config::format_profiles(&loaded_config));
+        return Ok(());
+    }
+    if cli.print_config {
+        println!("{}", config::format_config(&loaded_config, true)?);
+        return Ok(());
+    }
+    for (source, layer) in &loaded_config.layers {
+        let keys = layer.set_keys();
+        match source {
+            ConfigSource::Project(_) | ConfigSource::Profile(_) => {
+                println!("{source} sets: {}", keys.join(", "));
+            }
+            ConfigSource::Env if !keys.is_empty() => {
+                println!("{source} sets: {}", keys.join(", "));
+            }
+            _ => {}
+        }
+    }
+    let mut config = loaded_config.config;
+    let session_id = CheckpointStore::new_session_id();
+
+    let worktree = if cli.worktree {
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let worktree = Worktree::create(&cwd, &session_id)?;
+        config.accessible_paths = worktree.rebase_paths(&config.accessible_paths);
+        std::env::set_current_dir(worktree.rebase_path(&cwd))?;
+        println!(
+            "worktree: {} (branch {})",
+            worktree.path().display(),
+            worktree.branch()
+        );
+        if worktree.repo_has_uncommitted_changes() {
+            eprintln!(
+                "{}",
+                style("Uncommitted changes in the repository are not part of the worktree.")
+                    .yellow()
+            );
+        }
+        Some(worktree)
+    } else {
+        None
+    };
 
     let client = alors::client::initialize_client(&config)?;
     // Always print backend
-    println!("Backend: {:?}", config.backend);
+    println!("Backend: {}", config.backend);
+
+    let process_manager = Arc::new(Mutex::new(ProcessManager::new(config.process_log_lines)));
+    let lsp_manager = Arc::new(Mutex::new(LspManager::new(std::env::current_dir()?)));
 
     let mut tool_collection = ToolCollection::new();
     // Register tools
     tool_collection.register(Box::new(alors::tools::FileCreatorTool));
-    tool_collection.register(Box::new(alors::tools::FileEditorTool));
+    tool_collection.register(Box::new(alors::tools::FileEditorTool::new(
+        lsp_manager.clone(),
+    )));
     tool_collection.register(Box::new(alors::tools::FileReaderTool));
     tool_collection.register(Box::new(alors::tools::ListFilesTool));
+    tool_collection.register(Box::new(alors::tools::SearchFilesTool));
+    tool_collection.register(Box::new(alors::tools::FileOutlineTool));
     tool_collection.register(Box::new(alors::tools::ShellTool));
+    tool_collection.register(Box::new(alors::tools::ProcessTool::new(
+        process_manager.clone(),
+    )));
+    tool_collection.register(Box::new(alors::tools::CheckTool));
+    tool_collection.register(Box::new(alors::tools::RunTestsTool));
+    tool_collection.register(Box::new(alors::tools::LanguageServerTool::new(
+        lsp_manager.clone(),
+    )));
+    tool_collection.register(Box::new(alors::tools::GitTool));
+    tool_collection.register(Box::new(alors::tools::FetchUrlTool));
     // Collect tool names
     let schemas = tool_collection.get_all_schemas();
     let tool_names: Vec<String> = schemas
@@ -40,18 +115,39 @@ async fn main() -> Result<()> {
     }
     let tool_collection = Arc::new(tool_collection);
 
-    let mut agent = Agent::new(config.clone(), Some(client), tool_collection);
+    let mut agent = Agent::with_managers(
+        config.clone(),
+        Some(client),
+        tool_collection,
+        process_manager,
+        lsp_manager,
+    );
+    agent.worktree = worktree;
+    if agent.config.checkpoints {
+        agent.checkpoints = CheckpointStore::open(&std::env::current_dir()?, &session_id)?;
+        match &agent.checkpoints {
+            Some(store) => println!("checkpoints: {}", store.session_ref()),
+            None => eprintln!(
+                "{}",
+                style("Checkpoints are disabled: the current directory is not a git repository.")
+                    .yellow()
+            ),
+        }
+    }
 
     // Only process system prompt if one is configured
-    if let Some(system_prompt) = &agent.config.system_prompt {
-        let prompt_data = {
-            let mut fsm = agent.file_state_manager.lock().unwrap();
-            alors::prompt_builder::process_prompt(system_prompt, &agent.config, &mut fsm)?
-        };
-
+    agent.system_prompt =
+        SystemPromptTemplate::load(&agent.config, &std::env::current_dir()?, tool_names)?;
+    if let Some(path) = agent.system_prompt.as_ref().and_then(|t| t.path()) {
+        println!("system prompt: {}", path.display());
+    }
+    if let Some((rendered, prompt_data)) = agent.render_system_prompt()? {
+        if !prompt_data.instruction_files.is_empty() {
+            println!("instructions: {}", prompt_data.instruction_files.join(", "));
+        }
         if agent.config.show_system_prompt {
             println!("[{}]", style("system").blue());
-            println!("{system_prompt}"); // Print the original, un-expanded prompt
+            println!("{rendered}"); // Print the rendered template, before `@` expansion
 
             if !prompt_data.file_summaries.is_empty() {
                 println!("{}", style("Attached files:").dim());
@@ -76,7 +172,101 @@ async fn main() -> Result<()> {
     }
 
     let mut app = ui::App::new(agent);
-    app.run(&cli.prompt.unwrap_or_default()).await?;
+    let result = app.run(&cli.prompt.unwrap_or_default()).await;
+    app.shutdown();
+    result?;
 
     Ok(())
 }
+
+fn run_config_command(command: &cli::ConfigCommand, overrides: &ConfigLayer) -> Result<()> {
+    match command {
+        cli::ConfigCommand::Init => {
+            let path = config::user_config_path()?;
+            let added = config::init(&path)?;
+            if added.is_empty() {
+                println!("{} already contains all settings.", path.display());
+            } else {
+                println!("Added to {}: {}", path.display(), added.join(", "));
+            }
+        }
+        cli::ConfigCommand::Show { effective } => {
+            let loaded = config::load(overrides)?;
+            print_config_warnings(&loaded);
+            println!("{}", config::format_config(&loaded, *effective)?);
+        }
+        cli::ConfigCommand::Get { key } => {
+            let loaded = config::load(overrides)?;
+            print_config_warnings(&loaded);
+            println!("{}", config::get_value(&loaded.config, key)?);
+        }
+        cli::ConfigCommand::Set {
+            key,
+            value,
+            project,
+        } => {
+            let path = config_file_path(*project)?;
+            let content = config::read_file_to_update(&path)?;
+            let updated = config::set_value(&content, key, value, &path)?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, updated)?;
+            println!("Set {key} in {}", path.display());
+        }
+        cli::ConfigCommand::Trust => {
+            let path = config::find_project_config(&std::env::current_dir()?)
+                .ok_or_else(|| anyhow!("No project config file found."))?;
+            config::trust_project(&path, &config::trusted_projects_path()?)?;
+            println!("Trusted {}", path.display());
+        }
+        cli::ConfigCommand::Path => {
+            println!("user: {}", config::user_config_path()?.display());
+            if let Some(project_path) = config::find_project_config(&std::env::current_dir()?) {
+                println!("project: {}", project_path.display());
+            }
+        }
+        cli::ConfigCommand::Edit { project } => {
+            let path = config_file_path(*project)?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+            // The editor may contain arguments, like `code --wait`.
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("{editor} \"$1\""))
+                .arg("sh")
+                .arg(&path)
+                .status()?;
+            if !status.success() {
+                return Err(anyhow!("{editor} exited with {status}"));
+            }
+            let mut warnings = Vec::new();
+            let content = config::read_file_to_update(&path)?;
+            config::parse_layer(&content, &path, &mut warnings)?;
+            for warning in warnings {
+                eprintln!("{}", style(warning).yellow());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The user config file, or with `project` the project config file. If there is no project
+/// config file yet, `.alors.toml` in the working directory is used.
+fn config_file_path(project: bool) -> Result<std::path::PathBuf> {
+    if !project {
+        return config::user_config_path();
+    }
+    let cwd = std::env::current_dir()?;
+    Ok(config::find_project_config(&cwd).unwrap_or_else(|| cwd.join(".alors.toml")))
+}
+
+fn print_config_warnings(loaded: &config::LoadedConfig) {
+    for warning in &loaded.warnings {
+        eprintln!("{}", style(warning).yellow());
+    }
+}
@@ -1,7 +1,9 @@
+use anyhow::{Result, anyhow};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
 use std::collections::BTreeSet;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tempfile::NamedTempFile;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -52,6 +54,55 @@ pub fn expand_and_validate(paths: &[String], ignored_paths: &[String]) -> Expans
     }
 }
 
+/// Filters paths by include and exclude globs, e.g. `*.rs` or `src/**/*.ts`.
+///
+/// Globs are matched against the path relative to the current working directory,
+/// and `*` also matches `/`, so `*.rs` matches Rust files in any directory.
+#[derive(Debug)]
+pub struct GlobFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    project_root: PathBuf,
+}
+
+impl GlobFilter {
+    /// Creates a filter. An empty `include` list includes everything.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(Self::build_set(include)?)
+        };
+        Ok(Self {
+            include,
+            exclude: Self::build_set(exclude)?,
+            project_root: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        })
+    }
+
+    fn build_set(globs: &[String]) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            builder.add(Glob::new(glob).map_err(|e| anyhow!("Invalid glob '{glob}': {e}"))?);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Returns true if the path matches an include glob (if any) and no exclude glob.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        let relative = path
+            .strip_prefix(&self.project_root)
+            .or_else(|_| path.strip_prefix("."))
+            .unwrap_or(path);
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|set| set.is_match(relative));
+        included && !self.exclude.is_match(relative)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,4 +322,18 @@ mod tests {
         // Make sure other files are still there
         assert!(result.files.contains(&format!("{root}/file1.txt")));
     }
+
+    #[test]
+    fn test_glob_filter() {
+        let filter = GlobFilter::new(&["*.rs".to_string()], &["target/**".to_string()]).unwrap();
+        assert!(filter.matches("src/main.rs"));
+        assert!(filter.matches("./src/main.rs"));
+        assert!(!filter.matches("src/notes.txt"));
+        assert!(!filter.matches("target/debug/build.rs"));
+
+        let everything = GlobFilter::new(&[], &[]).unwrap();
+        assert!(everything.matches("any/file.txt"));
+
+        assert!(GlobFilter::new(&["a[".to_string()], &[]).is_err());
+    }
 }
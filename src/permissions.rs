@@ -3,7 +3,12 @@
 //! This module provides a centralized and clear way, preventing security-sensitive logic from being
 //! scattered across the codebase.
 
+use crate::backend::Backend;
+use crate::client;
+use crate::config::Config;
 use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::ffi::OsString;
 use std::path::Path;
 
 /// Checks if a given file path is within the list of accessible paths.
@@ -291,3 +296,322 @@ mod command_tests {
         assert!(is_command_allowed(command, &allowed_prefixes).is_err());
     }
 }
+
+/// The placeholder that replaces secret values in tool output.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Secret values shorter than this are not redacted, to avoid mangling
+/// unrelated output when a variable holds something like `1` or `true`.
+const MIN_SECRET_LEN: usize = 8;
+
+/// Checks if an environment variable name matches a pattern.
+/// Patterns are matched case-sensitively, and `*` matches any sequence of characters.
+pub fn matches_env_pattern(name: &str, pattern: &str) -> bool {
+    let regex_str = format!("^{}$", regex::escape(pattern).replace(r"\*", ".*"));
+    Regex::new(&regex_str).is_ok_and(|re| re.is_match(name))
+}
+
+/// Returns the names of the variables holding API keys for any backend or custom provider,
+/// including the `${VAR}` references in provider headers.
+/// These are never passed on to spawned commands.
+pub fn api_key_env_vars(config: &Config) -> Vec<String> {
+    Backend::BUILT_IN
+        .iter()
+        .filter_map(|backend| backend.config()?.api_key_env_var)
+        .chain(
+            config
+                .providers
+                .values()
+                .filter_map(|provider| provider.api_key_env_var.clone()),
+        )
+        .chain(
+            config
+                .providers
+                .values()
+                .flat_map(|provider| provider.headers.values())
+                .flat_map(|value| client::referenced_env_vars(value)),
+        )
+        .collect()
+}
+
+fn is_env_var_denied(name: &str, config: &Config) -> bool {
+    api_key_env_vars(config).iter().any(|var| var == name)
+        || config
+            .denied_env_vars
+            .iter()
+            .any(|pattern| matches_env_pattern(name, pattern))
+}
+
+/// Filters environment variables for a spawned command.
+///
+/// If `allowed_env_vars` is non-empty, only matching variables are kept.
+/// Variables matching `denied_env_vars` and API key variables are always removed.
+/// Names and values don't have to be UTF-8; names are matched in their lossy form.
+pub fn filter_env(
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+    config: &Config,
+) -> Vec<(OsString, OsString)> {
+    vars.into_iter()
+        .filter(|(name, _)| {
+            let name = name.to_string_lossy();
+            (config.allowed_env_vars.is_empty()
+                || config
+                    .allowed_env_vars
+                    .iter()
+                    .any(|pattern| matches_env_pattern(&name, pattern)))
+                && !is_env_var_denied(&name, config)
+        })
+        .collect()
+}
+
+/// The environment to use for commands spawned by the agent.
+pub fn command_env(config: &Config) -> Vec<(OsString, OsString)> {
+    filter_env(std::env::vars_os(), config)
+}
+
+/// Collects the values of all denied variables from `vars`.
+/// These are the secrets that must never reach the LLM.
+pub fn secret_values(
+    vars: impl IntoIterator<Item = (String, String)>,
+    config: &Config,
+) -> Vec<String> {
+    let mut secrets: Vec<String> = vars
+        .into_iter()
+        .filter(|(name, value)| value.len() >= MIN_SECRET_LEN && is_env_var_denied(name, config))
+        .map(|(_, value)| value)
+        .collect();
+    // Replace longer secrets first, in case one secret contains another.
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets.dedup();
+    secrets
+}
+
+/// Replaces every occurrence of a secret in `text` with a placeholder.
+pub fn redact(text: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(text.to_string(), |redacted, secret| {
+        redacted.replace(secret.as_str(), REDACTED)
+    })
+}
+
+/// Redacts the values of all secret variables in the current environment from `text`.
+/// Values that aren't UTF-8 can't appear in `text`, so they are skipped.
+pub fn redact_secrets(text: &str, config: &Config) -> String {
+    let vars = std::env::vars_os().filter_map(|(name, value)| {
+        Some((
+            name.to_string_lossy().into_owned(),
+            value.into_string().ok()?,
+        ))
+    });
+    redact(text, &secret_values(vars, config))
+}
+
+#[cfg(test)]
+mod env_tests {
+    use super::*;
+    use crate::backend::ProviderConfig;
+    use std::collections::BTreeMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn os_vars(vars: Vec<(String, String)>) -> Vec<(OsString, OsString)> {
+        vars.into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect()
+    }
+
+    fn names(vars: &[(OsString, OsString)]) -> Vec<&str> {
+        vars.iter()
+            .map(|(name, _)| name.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_env_pattern_matching() {
+        assert!(matches_env_pattern("PATH", "PATH"));
+        assert!(!matches_env_pattern("PATH_EXTRA", "PATH"));
+        assert!(matches_env_pattern("AWS_SECRET_ACCESS_KEY", "*SECRET*"));
+        assert!(matches_env_pattern("LC_ALL", "LC_*"));
+        assert!(!matches_env_pattern("path", "PATH"));
+    }
+
+    #[test]
+    fn test_api_keys_are_always_removed() {
+        let config = Config {
+            denied_env_vars: vec![],
+            ..Default::default()
+        };
+        let env = vars(&[
+            ("PATH", "/usr/bin"),
+            ("OPENROUTER_API_KEY", "sk-or-secret"),
+            ("OPENAI_API_KEY", "sk-secret"),
+        ]);
+        assert_eq!(names(&filter_env(os_vars(env), &config)), vec!["PATH"]);
+    }
+
+    #[test]
+    fn test_provider_header_vars_are_removed() {
+        let mut config = Config {
+            denied_env_vars: vec![],
+            ..Default::default()
+        };
+        config.providers.insert(
+            "internal".to_string(),
+            ProviderConfig {
+                headers: BTreeMap::from([(
+                    "X-Auth".to_string(),
+                    "Token ${INTERNAL_TOKEN}:${INTERNAL_TEAM}".to_string(),
+                )]),
+                ..Default::default()
+            },
+        );
+        let env = vars(&[
+            ("PATH", "/usr/bin"),
+            ("INTERNAL_TOKEN", "tok-secret-value"),
+            ("INTERNAL_TEAM", "platform"),
+        ]);
+        assert_eq!(
+            names(&filter_env(os_vars(env.clone()), &config)),
+            vec!["PATH"]
+        );
+        assert_eq!(
+            secret_values(env, &config),
+            vec!["tok-secret-value", "platform"]
+        );
+    }
+
+    #[test]
+    fn test_allowlist_and_denylist() {
+        let config = Config {
+            allowed_env_vars: vec!["PATH".to_string(), "MY_*".to_string()],
+            denied_env_vars: vec!["MY_PASSWORD".to_string()],
+            ..Default::default()
+        };
+        let env = vars(&[
+            ("PATH", "/usr/bin"),
+            ("HOME", "/home/user"),
+            ("MY_SETTING", "1"),
+            ("MY_PASSWORD", "hunter22"),
+        ]);
+        assert_eq!(
+            names(&filter_env(os_vars(env), &config)),
+            vec!["PATH", "MY_SETTING"]
+        );
+    }
+
+    #[test]
+    fn test_default_denylist_removes_common_secrets() {
+        let config = Config::default();
+        let env = vars(&[
+            ("PATH", "/usr/bin"),
+            ("GITHUB_TOKEN", "ghp_abc"),
+            ("AWS_SECRET_ACCESS_KEY", "abc"),
+            ("ANTHROPIC_API_KEY", "abc"),
+        ]);
+        assert_eq!(names(&filter_env(os_vars(env), &config)), vec!["PATH"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_variables_are_kept() {
+        use std::os::unix::ffi::OsStringExt;
+        let config = Config::default();
+        let value = OsString::from_vec(vec![b'a', 0xff]);
+        let env = vec![
+            (OsString::from("LANG_BYTES"), value.clone()),
+            (OsString::from_vec(vec![b'X', 0xff]), OsString::from("1")),
+            (OsString::from("MY_TOKEN"), value.clone()),
+        ];
+
+        let filtered = filter_env(env, &config);
+
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[0], (OsString::from("LANG_BYTES"), value));
+    }
+
+    #[test]
+    fn test_secret_values_are_redacted() {
+        let config = Config::default();
+        let env = vars(&[
+            ("OPENROUTER_API_KEY", "sk-or-v1-0123456789"),
+            ("DB_PASSWORD", "short"),
+            ("HOME", "/home/user"),
+        ]);
+        let secrets = secret_values(env, &config);
+        assert_eq!(secrets, vec!["sk-or-v1-0123456789".to_string()]);
+
+        let output = "key=sk-or-v1-0123456789 home=/home/user password=short";
+        assert_eq!(
+            redact(output, &secrets),
+            "key=[REDACTED] home=/home/user password=short"
+        );
+    }
+}
+
+/// Checks if a host matches a domain pattern.
+/// A pattern matches the domain itself and all of its subdomains, so `rust-lang.org`
+/// matches `doc.rust-lang.org`.
+pub fn matches_domain(host: &str, pattern: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let pattern = pattern.trim().trim_start_matches("*.").to_lowercase();
+    host == pattern || host.ends_with(&format!(".{pattern}"))
+}
+
+/// Checks if a host may be fetched at all, based on the `denied_domains` blacklist.
+pub fn is_domain_denied(host: &str, denied_domains: &[String]) -> Result<()> {
+    match denied_domains
+        .iter()
+        .find(|pattern| matches_domain(host, pattern))
+    {
+        Some(pattern) => Err(anyhow!(
+            "Domain `{host}` is denied by the pattern `{pattern}` in `denied_domains`."
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Checks if a host may be fetched without confirmation, based on a domain whitelist.
+pub fn is_domain_allowed(host: &str, allowed_domains: &[String]) -> Result<()> {
+    if allowed_domains.is_empty() {
+        return Ok(()); // If whitelist is empty, all domains are allowed.
+    }
+    if allowed_domains
+        .iter()
+        .any(|pattern| matches_domain(host, pattern))
+    {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Domain `{host}` is not in the allowed domains: {allowed_domains:?}."
+        ))
+    }
+}
+
+#[cfg(test)]
+mod domain_tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_matching() {
+        assert!(matches_domain("docs.rs", "docs.rs"));
+        assert!(matches_domain("doc.rust-lang.org", "rust-lang.org"));
+        assert!(matches_domain("Doc.Rust-Lang.org.", "*.rust-lang.org"));
+        assert!(!matches_domain("evil-rust-lang.org", "rust-lang.org"));
+    }
+
+    #[test]
+    fn test_domain_policy() {
+        let denied = vec!["internal.example.com".to_string()];
+        assert!(is_domain_denied("api.internal.example.com", &denied).is_err());
+        assert!(is_domain_denied("example.com", &denied).is_ok());
+
+        let allowed = vec!["docs.rs".to_string()];
+        assert!(is_domain_allowed("docs.rs", &allowed).is_ok());
+        assert!(is_domain_allowed("example.com", &allowed).is_err());
+        assert!(is_domain_allowed("example.com", &[]).is_ok());
+    }
+}
@@ -0,0 +1,486 @@
+//! # Background Process Manager
+//!
+//! Long-running commands like dev servers or file watchers never exit, so they cannot be
+//! run through `execute_shell_command`, which waits for the child to finish. The
+//! `ProcessManager` starts such commands in the background, keeps their output in a
+//! bounded ring buffer that the LLM can poll, and makes sure they are terminated when
+//! the agent shuts down.
+//!
+//! Every process is spawned in its own process group, so stopping it also stops any
+//! children it spawned (e.g. `sh -c "npm run dev"` starting `node`).
+
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsString;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// How long `stop` waits for a process to exit after SIGTERM before killing it.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// The output stream a log line was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub stream: OutputStream,
+    pub text: String,
+}
+
+/// A bounded buffer holding the most recent output lines of a process.
+///
+/// ### Reasoning
+/// Lines are addressed by an absolute offset (the number of lines written before them),
+/// not by their position in the buffer. This lets the LLM poll with the `next_offset`
+/// of its previous read and only receive new lines, even after old lines were evicted.
+#[derive(Debug)]
+pub struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+    /// The total number of lines ever pushed into this buffer.
+    total_lines: usize,
+}
+
+/// The result of reading a window of lines from a `LogBuffer`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LogSlice {
+    /// The absolute offset of the first returned line.
+    pub start_offset: usize,
+    pub lines: Vec<LogLine>,
+    /// The offset to pass to the next read to continue where this one stopped.
+    pub next_offset: usize,
+    /// The number of requested lines that were already evicted from the buffer.
+    pub evicted: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity: capacity.max(1),
+            total_lines: 0,
+        }
+    }
+
+    pub fn push(&mut self, stream: OutputStream, text: String) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(LogLine { stream, text });
+        self.total_lines += 1;
+    }
+
+    /// The absolute offset of the oldest line still held in the buffer.
+    fn first_offset(&self) -> usize {
+        self.total_lines - self.lines.len()
+    }
+
+    /// Reads up to `limit` lines starting at `offset`.
+    /// If `offset` is `None`, the last `limit` lines are returned (like `tail`).
+    pub fn read(&self, offset: Option<usize>, limit: usize) -> LogSlice {
+        let first_offset = self.first_offset();
+        let requested = offset.unwrap_or_else(|| self.total_lines.saturating_sub(limit));
+        let start_offset = requested.clamp(first_offset, self.total_lines);
+        let evicted = start_offset - requested.min(start_offset);
+
+        let lines: Vec<LogLine> = self
+            .lines
+            .iter()
+            .skip(start_offset - first_offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        let next_offset = start_offset + lines.len();
+
+        LogSlice {
+            start_offset,
+            lines,
+            next_offset,
+            evicted,
+        }
+    }
+}
+
+/// A command running in the background, started through the `ProcessManager`.
+pub struct BackgroundProcess {
+    pub id: usize,
+    pub command: String,
+    pub workdir: Option<String>,
+    pub pid: Option<u32>,
+    started_at: Instant,
+    logs: Arc<Mutex<LogBuffer>>,
+    stdin: mpsc::UnboundedSender<String>,
+    exit_status: watch::Receiver<Option<ExitStatus>>,
+    kill: Option<oneshot::Sender<()>>,
+}
+
+impl BackgroundProcess {
+    /// Returns the exit status if the process has terminated.
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        *self.exit_status.borrow()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.exit_status().is_none()
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// A short, human- and LLM-readable description of the process state.
+    pub fn describe_state(&self) -> String {
+        match self.exit_status() {
+            None => "running".to_string(),
+            Some(status) => describe_exit_status(&status),
+        }
+    }
+
+    pub fn read_logs(&self, offset: Option<usize>, limit: usize) -> LogSlice {
+        self.logs.lock().unwrap().read(offset, limit)
+    }
+
+    /// Sends SIGTERM to the process group, falling back to killing the child directly.
+    fn terminate(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            // With `process_group(0)`, the process group id equals the child's pid.
+            let signalled = std::process::Command::new("kill")
+                .args(["-TERM", "--", &format!("-{pid}")])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+            if signalled {
+                return;
+            }
+        }
+        self.force_kill();
+    }
+
+    fn force_kill(&mut self) {
+        if let Some(kill) = self.kill.take() {
+            kill.send(()).ok();
+        }
+    }
+}
+
+pub fn describe_exit_status(status: &ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with code {code}"),
+        None => "terminated by signal".to_string(),
+    }
+}
+
+/// Owns all background processes of an agent session.
+pub struct ProcessManager {
+    processes: BTreeMap<usize, BackgroundProcess>,
+    next_id: usize,
+    log_capacity: usize,
+}
+
+impl ProcessManager {
+    /// Creates a manager whose processes each keep at most `log_capacity` output lines.
+    pub fn new(log_capacity: usize) -> Self {
+        Self {
+            processes: BTreeMap::new(),
+            next_id: 1,
+            log_capacity,
+        }
+    }
+
+    /// Spawns `command` through `sh -c` with exactly the environment variables in `env`
+    /// and starts collecting its output. Must be called from within a Tokio runtime.
+    pub fn start(
+        &mut self,
+        command: &str,
+        workdir: Option<&str>,
+        env: &[(OsString, OsString)],
+    ) -> Result<&BackgroundProcess> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(command)
+            .env_clear()
+            .envs(env.iter().cloned())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        cmd.process_group(0);
+        if let Some(dir) = workdir {
+            cmd.current_dir(dir);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start `{command}`: {e}"))?;
+        let pid = child.id();
+
+        let logs = Arc::new(Mutex::new(LogBuffer::new(self.log_capacity)));
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(collect_lines(stdout, OutputStream::Stdout, logs.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(collect_lines(stderr, OutputStream::Stderr, logs.clone()));
+        }
+
+        let (stdin_tx, mut stdin_rx) = mpsc::unbounded_channel::<String>();
+        if let Some(mut stdin) = child.stdin.take() {
+            tokio::spawn(async move {
+                while let Some(input) = stdin_rx.recv().await {
+                    if stdin.write_all(input.as_bytes()).await.is_err()
+                        || stdin.flush().await.is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // The waiter task owns the child. Dropping it (e.g. on runtime shutdown)
+        // kills the process thanks to `kill_on_drop`.
+        let (status_tx, status_rx) = watch::channel(None);
+        let (kill_tx, kill_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = kill_rx => {
+                    child.start_kill().ok();
+                    child.wait().await
+                }
+            };
+            if let Ok(status) = status {
+                status_tx.send(Some(status)).ok();
+            }
+        });
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.processes.insert(
+            id,
+            BackgroundProcess {
+                id,
+                command: command.to_string(),
+                workdir: workdir.map(str::to_string),
+                pid,
+                started_at: Instant::now(),
+                logs,
+                stdin: stdin_tx,
+                exit_status: status_rx,
+                kill: Some(kill_tx),
+            },
+        );
+        Ok(&self.processes[&id])
+    }
+
+    pub fn get(&self, id: usize) -> Result<&BackgroundProcess> {
+        self.processes
+            .get(&id)
+            .ok_or_else(|| anyhow!("No background process with id {id}."))
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &BackgroundProcess> {
+        self.processes.values()
+    }
+
+    /// Writes `input` to the stdin of a running process.
+    pub fn send_input(&self, id: usize, input: &str) -> Result<()> {
+        let process = self.get(id)?;
+        if !process.is_running() {
+            return Err(anyhow!(
+                "Process {id} is not running ({}).",
+                process.describe_state()
+            ));
+        }
+        process
+            .stdin
+            .send(input.to_string())
+            .map_err(|_| anyhow!("The stdin of process {id} is closed."))
+    }
+
+    /// Asks a process to terminate and returns a receiver for its exit status.
+    /// Use `wait_for_exit` to await the result without holding the manager's lock.
+    pub fn request_stop(&mut self, id: usize) -> Result<watch::Receiver<Option<ExitStatus>>> {
+        let process = self
+            .processes
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("No background process with id {id}."))?;
+        if process.is_running() {
+            process.terminate();
+        }
+        Ok(process.exit_status.clone())
+    }
+
+    /// Kills a process that did not react to `request_stop` in time.
+    pub fn force_kill(&mut self, id: usize) {
+        if let Some(process) = self.processes.get_mut(&id) {
+            process.force_kill();
+        }
+    }
+
+    /// Terminates all running processes. Called when the agent shuts down.
+    pub fn stop_all(&mut self) {
+        for process in self.processes.values_mut() {
+            if process.is_running() {
+                process.terminate();
+            }
+        }
+    }
+}
+
+impl Drop for ProcessManager {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+/// Waits until the process behind `exit_status` has terminated, up to the grace period.
+/// Returns `None` if it is still running afterwards.
+pub async fn wait_for_exit(
+    mut exit_status: watch::Receiver<Option<ExitStatus>>,
+) -> Option<ExitStatus> {
+    let waited = tokio::time::timeout(STOP_GRACE_PERIOD, async {
+        exit_status.wait_for(|s| s.is_some()).await.map(|s| *s)
+    })
+    .await;
+    match waited {
+        Ok(Ok(status)) => status,
+        _ => *exit_status.borrow(),
+    }
+}
+
+async fn collect_lines(
+    reader: impl AsyncRead + Unpin,
+    stream: OutputStream,
+    logs: Arc<Mutex<LogBuffer>>,
+) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        logs.lock().unwrap().push(stream, line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> LogLine {
+        LogLine {
+            stream: OutputStream::Stdout,
+            text: text.to_string(),
+        }
+    }
+
+    fn buffer_with(capacity: usize, count: usize) -> LogBuffer {
+        let mut buffer = LogBuffer::new(capacity);
+        for i in 0..count {
+            buffer.push(OutputStream::Stdout, format!("line {i}"));
+        }
+        buffer
+    }
+
+    async fn wait_for_logs(manager: &Arc<Mutex<ProcessManager>>, id: usize, expected: &str) {
+        for _ in 0..100 {
+            let slice = manager
+                .lock()
+                .unwrap()
+                .get(id)
+                .unwrap()
+                .read_logs(None, 100);
+            if slice.lines.iter().any(|l| l.text == expected) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Expected log line '{expected}' did not appear");
+    }
+
+    #[test]
+    fn test_log_buffer_tail() {
+        let buffer = buffer_with(10, 5);
+        let slice = buffer.read(None, 2);
+        assert_eq!(slice.lines, vec![line("line 3"), line("line 4")]);
+        assert_eq!(slice.start_offset, 3);
+        assert_eq!(slice.next_offset, 5);
+        assert_eq!(slice.evicted, 0);
+    }
+
+    #[test]
+    fn test_log_buffer_read_from_offset() {
+        let buffer = buffer_with(10, 5);
+        let slice = buffer.read(Some(1), 2);
+        assert_eq!(slice.lines, vec![line("line 1"), line("line 2")]);
+        assert_eq!(slice.next_offset, 3);
+
+        let past_end = buffer.read(Some(5), 10);
+        assert!(past_end.lines.is_empty());
+        assert_eq!(past_end.next_offset, 5);
+    }
+
+    #[test]
+    fn test_log_buffer_evicts_oldest_lines() {
+        let buffer = buffer_with(3, 5);
+        let slice = buffer.read(Some(0), 10);
+        assert_eq!(
+            slice.lines,
+            vec![line("line 2"), line("line 3"), line("line 4")]
+        );
+        assert_eq!(slice.start_offset, 2);
+        assert_eq!(slice.evicted, 2);
+    }
+
+    #[tokio::test]
+    async fn test_start_and_collect_output() {
+        let manager = Arc::new(Mutex::new(ProcessManager::new(100)));
+        let id = manager
+            .lock()
+            .unwrap()
+            .start("echo out; echo err >&2", None, &[])
+            .unwrap()
+            .id;
+
+        wait_for_logs(&manager, id, "err").await;
+        wait_for_logs(&manager, id, "out").await;
+
+        let exit = manager.lock().unwrap().get(id).unwrap().exit_status.clone();
+        let status = wait_for_exit(exit).await.unwrap();
+        assert_eq!(status.code(), Some(0));
+
+        let slice = manager.lock().unwrap().get(id).unwrap().read_logs(None, 10);
+        assert!(slice.lines.contains(&LogLine {
+            stream: OutputStream::Stderr,
+            text: "err".to_string()
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_send_input_and_stop() {
+        let manager = Arc::new(Mutex::new(ProcessManager::new(100)));
+        let id = manager.lock().unwrap().start("cat", None, &[]).unwrap().id;
+
+        manager.lock().unwrap().send_input(id, "hello\n").unwrap();
+        wait_for_logs(&manager, id, "hello").await;
+
+        let exit = manager.lock().unwrap().request_stop(id).unwrap();
+        assert!(wait_for_exit(exit).await.is_some());
+        assert!(!manager.lock().unwrap().get(id).unwrap().is_running());
+        assert!(manager.lock().unwrap().send_input(id, "again").is_err());
+    }
+
+    #[test]
+    fn test_unknown_process_id() {
+        let manager = ProcessManager::new(100);
+        let result = manager.get(42);
+        assert!(result.is_err());
+        assert!(result.err().unwrap().to_string().contains("id 42"));
+    }
+}
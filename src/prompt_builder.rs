@@ -1,6 +1,6 @@
-use crate::{enricher, file_state_manager::FileStateManager, path_expander};
+use crate::{config::Config, enricher, file_state_manager::FileStateManager, path_expander};
 use anyhow::Result;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Default, PartialEq)]
 pub struct PromptData {
@@ -8,11 +8,89 @@ pub struct PromptData {
     pub file_summaries: Vec<String>,
     pub warnings: Vec<String>,
     pub has_mentions: bool,
+    /// The instruction files attached to a system prompt, relative to the repository root.
+    pub instruction_files: Vec<String>,
+}
+
+/// Processes the system prompt like a user prompt, then attaches the instruction files
+/// found for `dir`, unless `include_instruction_files` is off.
+pub fn process_system_prompt(
+    system_prompt: &str,
+    config: &Config,
+    file_state_manager: &mut FileStateManager,
+    dir: &Path,
+) -> Result<PromptData> {
+    let mut prompt_data = process_prompt(system_prompt, config, file_state_manager)?;
+    if !config.include_instruction_files {
+        return Ok(prompt_data);
+    }
+
+    let root = repository_root(dir);
+    let mut instructions = String::new();
+    for path in find_instruction_files(config, dir) {
+        let path_str = path.to_string_lossy().to_string();
+        match file_state_manager.open_file(&path_str) {
+            Ok(file_state) => {
+                instructions.push_str(&file_state.display_lif_contents());
+                instructions.push('\n');
+                let relative = path.strip_prefix(&root).unwrap_or(&path);
+                prompt_data
+                    .instruction_files
+                    .push(relative.display().to_string());
+            }
+            Err(e) => prompt_data
+                .warnings
+                .push(format!("Failed to open instruction file {path_str}: {e}")),
+        }
+    }
+    if !instructions.is_empty() {
+        prompt_data.final_prompt.push_str(
+            "\n\nProject instructions, from the repository root down to the working directory. Follow them:\n",
+        );
+        prompt_data.final_prompt.push_str(&instructions);
+    }
+    Ok(prompt_data)
+}
+
+/// Finds the `instruction_files` in each directory from the repository root down to `dir`,
+/// so that more specific instructions come last. Outside of a git repository, only `dir`
+/// is searched. Files that resolve outside the repository, e.g. through a symlink, are skipped.
+pub fn find_instruction_files(config: &Config, dir: &Path) -> Vec<PathBuf> {
+    let root = repository_root(dir);
+    let canonical_root = root.canonicalize().unwrap_or_else(|_| root.clone());
+    let mut directories: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|ancestor| ancestor.starts_with(&root))
+        .collect();
+    directories.reverse();
+    directories
+        .into_iter()
+        .flat_map(|directory| {
+            config
+                .instruction_files
+                .iter()
+                .map(move |name| directory.join(name))
+        })
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .canonicalize()
+                    .is_ok_and(|canonical| canonical.starts_with(&canonical_root))
+        })
+        .collect()
+}
+
+/// The nearest directory containing `.git`, or `dir` itself.
+fn repository_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
 }
 
 pub fn process_prompt(
     original_prompt: &str,
-    config: &crate::config::Config,
+    config: &Config,
     file_state_manager: &mut FileStateManager,
 ) -> Result<PromptData> {
     let enrichments = enricher::extract_enrichments(original_prompt);
@@ -82,6 +160,7 @@ pub fn process_prompt(
             warnings,
             file_summaries,
             has_mentions: !enrichments.mentioned_files.is_empty(),
+            ..Default::default()
         });
     }
 
@@ -110,6 +189,7 @@ pub fn process_prompt(
         file_summaries,
         warnings,
         has_mentions: !enrichments.mentioned_files.is_empty(),
+        ..Default::default()
     })
 }
 
@@ -179,4 +259,56 @@ mod tests {
         assert!(result.final_prompt.contains("Hello, world!"));
         assert!(result.final_prompt.contains("nonexistent.txt"));
     }
+
+    #[test]
+    fn test_instruction_files_from_repository_root_down() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("crates/core");
+        fs::create_dir_all(&sub).unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join("AGENTS.md"), "Use tabs.").unwrap();
+        fs::write(root.join(".cursorrules"), "Be brief.").unwrap();
+        fs::write(sub.join("CLAUDE.md"), "No unsafe code.").unwrap();
+        fs::write(root.join("crates/NOTES.md"), "Not an instruction file.").unwrap();
+        let mut config = config::Config::default();
+        let mut fsm = FileStateManager::new();
+
+        let result = process_system_prompt("You are helpful.", &config, &mut fsm, &sub).unwrap();
+
+        assert_eq!(
+            result.instruction_files,
+            vec![".cursorrules", "AGENTS.md", "crates/core/CLAUDE.md"]
+        );
+        assert!(
+            result
+                .final_prompt
+                .starts_with("You are helpful.\n\nProject instructions")
+        );
+        let position = |text| result.final_prompt.find(text).unwrap();
+        assert!(position("Be brief.") < position("Use tabs."));
+        assert!(position("Use tabs.") < position("No unsafe code."));
+        assert!(!result.final_prompt.contains("Not an instruction file."));
+
+        config.include_instruction_files = false;
+        let result = process_system_prompt("You are helpful.", &config, &mut fsm, &sub).unwrap();
+        assert_eq!(result.final_prompt, "You are helpful.");
+        assert!(result.instruction_files.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_instruction_files_outside_repository_are_skipped() {
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("secret"), "secret").unwrap();
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join(".git")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), root.join("AGENTS.md")).unwrap();
+        fs::write(root.join("CLAUDE.md"), "No unsafe code.").unwrap();
+
+        let files = find_instruction_files(&config::Config::default(), root);
+
+        assert_eq!(files, vec![root.join("CLAUDE.md")]);
+    }
 }
@@ -1,21 +1,152 @@
+use crate::client::{ApiRequest, ChatClient, TransientError};
+use crate::config::Config;
 use anyhow::Result;
 use console::style;
 use futures::StreamExt;
-use openrouter_api::{
-    OpenRouterClient, Ready,
-    models::tool::{FunctionCall, ToolCall},
-};
+use openrouter_api::models::tool::{FunctionCall, ToolCall};
 use openrouter_api::{models::tool::ToolCallChunk, types::chat::*};
+use rand::Rng;
 use std::collections::HashMap;
 use std::io::{Write, stdout};
+use std::time::Duration;
+
+/// How requests that failed with a `TransientError` are retried, and which models are tried
+/// when the retries are used up.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// The delay before the first retry. It doubles with each retry.
+    pub base_delay: Duration,
+    pub fallbacks: Vec<FallbackModel>,
+}
+
+/// A model from `fallback_models`, resolved like `model`.
+#[derive(Debug, Clone)]
+pub struct FallbackModel {
+    /// The name in the configuration, which may be an alias.
+    pub name: String,
+    pub id: String,
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let fallbacks = config
+            .fallback_models
+            .iter()
+            .map(|model| {
+                let mut fallback_config = config.clone();
+                fallback_config.model = model.clone();
+                FallbackModel {
+                    name: model.clone(),
+                    id: fallback_config.model_id(),
+                    parameters: fallback_config.model_parameters(),
+                }
+            })
+            .collect();
+        Self {
+            max_retries: config.max_retries,
+            base_delay: Duration::from_millis(config.retry_delay_ms),
+            fallbacks,
+        }
+    }
+
+    /// The delay before the retry after `retries` earlier retries. The backoff is scaled by a
+    /// random factor between 0.5 and 1, so that clients hitting the same limit spread out.
+    /// A longer `Retry-After` of the server wins.
+    fn delay(&self, retries: u32, error: &TransientError) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retries))
+            .mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        error
+            .retry_after
+            .map_or(backoff, |retry_after| retry_after.max(backoff))
+    }
+}
+
+/// Sends the request until it succeeds. Transient errors are retried with backoff, and when
+/// the retries are used up, the fallback models are tried in order. Other errors are returned
+/// right away. Each retry and fallback is reported in the scrollback.
+async fn send_with_retries(
+    client: &ChatClient,
+    mut request: ApiRequest,
+    policy: &RetryPolicy,
+) -> Result<Option<Message>> {
+    let mut fallbacks = policy.fallbacks.iter();
+    loop {
+        let mut retries = 0;
+        let error = loop {
+            let result = if request.completion.stream == Some(true) {
+                stream_and_collect_once(client, &request).await
+            } else {
+                collect_response_once(client, &request).await
+            };
+            let error = match result {
+                Ok(message) => return Ok(message),
+                Err(error) => error,
+            };
+            let Some(transient) = error.downcast_ref::<TransientError>() else {
+                return Err(error);
+            };
+            if retries == policy.max_retries {
+                break error;
+            }
+            let delay = policy.delay(retries, transient);
+            retries += 1;
+            println!(
+                "{}",
+                style(format!(
+                    "[Retry] {error}\nRetrying in {:.1}s ({retries}/{}).",
+                    delay.as_secs_f64(),
+                    policy.max_retries
+                ))
+                .yellow()
+            );
+            tokio::time::sleep(delay).await;
+        };
+
+        let Some(fallback) = fallbacks.next() else {
+            return Err(error);
+        };
+        println!(
+            "{}",
+            style(format!(
+                "[Fallback] {} failed: {error}\nFalling back to {}.",
+                request.completion.model, fallback.name
+            ))
+            .yellow()
+        );
+        request.completion.model = fallback.id.clone();
+        request.parameters = fallback.parameters.clone();
+    }
+}
 
 pub async fn collect_response_non_streaming(
-    client: &OpenRouterClient<Ready>,
-    mut request: ChatCompletionRequest,
+    client: &ChatClient,
+    mut request: ApiRequest,
+    policy: &RetryPolicy,
 ) -> Result<Option<Message>> {
-    request.stream = Some(false);
+    request.completion.stream = Some(false);
+    send_with_retries(client, request, policy).await
+}
 
-    let response = client.chat()?.chat_completion(request).await?;
+/// Streams a chat completion request to the console and collects the full response.
+/// If the stream is interrupted, the request is sent again and the response starts over.
+pub async fn stream_and_collect_response(
+    client: &ChatClient,
+    mut request: ApiRequest,
+    policy: &RetryPolicy,
+) -> Result<Option<Message>> {
+    request.completion.stream = Some(true);
+    send_with_retries(client, request, policy).await
+}
+
+async fn collect_response_once(
+    client: &ChatClient,
+    request: &ApiRequest,
+) -> Result<Option<Message>> {
+    let response = client.chat_completion(request).await?;
 
     if let Some(choice) = response.choices.first() {
         Ok(Some(choice.message.clone()))
@@ -24,13 +155,11 @@ pub async fn collect_response_non_streaming(
     }
 }
 
-/// Streams a chat completion request to the console and collects the full response.
-pub async fn stream_and_collect_response(
-    client: &OpenRouterClient<Ready>,
-    mut request: ChatCompletionRequest,
+async fn stream_and_collect_once(
+    client: &ChatClient,
+    request: &ApiRequest,
 ) -> Result<Option<Message>> {
-    request.stream = Some(true);
-    let mut stream = client.chat()?.chat_completion_stream(request);
+    let mut stream = client.chat_completion_stream(request).await?;
 
     let mut content = String::new();
     let mut tool_call_chunks: HashMap<u32, ToolCallChunk> = HashMap::new();
@@ -125,3 +254,149 @@ pub async fn stream_and_collect_response(
         tool_call_id: None,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Backend;
+    use crate::client::initialize_client;
+    use crate::client::test_server::{response, serve};
+    use crate::config::ConfigLayer;
+
+    const HELLO_STREAM: &str = "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hello\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n";
+
+    fn config(base_url: &str, fallback_models: Vec<String>) -> Config {
+        let mut config = Config::default();
+        config.merge(&ConfigLayer {
+            backend: Some(Backend::Ollama),
+            base_url: Some(base_url.to_string()),
+            model: Some("primary".to_string()),
+            max_retries: Some(2),
+            retry_delay_ms: Some(1),
+            fallback_models,
+            ..Default::default()
+        });
+        config
+    }
+
+    fn request(config: &Config) -> ApiRequest {
+        ApiRequest {
+            completion: ChatCompletionRequest {
+                model: config.model_id(),
+                messages: vec![],
+                tools: None,
+                stream: None,
+                response_format: None,
+                provider: None,
+                models: None,
+                transforms: None,
+            },
+            parameters: config.model_parameters(),
+        }
+    }
+
+    async fn stream(config: &Config) -> Result<Option<Message>> {
+        let client = initialize_client(config).unwrap();
+        let policy = RetryPolicy::from_config(config);
+        stream_and_collect_response(&client, request(config), &policy).await
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried() {
+        let unavailable = response("503 Service Unavailable", "text/plain", "overloaded");
+        let (base_url, request_log) = serve(vec![
+            unavailable.clone(),
+            unavailable,
+            response("200 OK", "text/event-stream", HELLO_STREAM),
+        ])
+        .await;
+
+        let message = stream(&config(&base_url, vec![])).await.unwrap().unwrap();
+
+        assert_eq!(message.content, "Hello");
+        assert_eq!(request_log.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_stream_is_retried() {
+        // The connection closes before the announced length is sent.
+        let interrupted = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: 1000\r\nConnection: close\r\n\r\n{}",
+            &HELLO_STREAM[..HELLO_STREAM.len() - "data: [DONE]\n\n".len()]
+        );
+        let (base_url, request_log) = serve(vec![
+            interrupted,
+            response("200 OK", "text/event-stream", HELLO_STREAM),
+        ])
+        .await;
+
+        let message = stream(&config(&base_url, vec![])).await.unwrap().unwrap();
+
+        assert_eq!(message.content, "Hello");
+        assert_eq!(request_log.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_models_are_tried_in_order() {
+        let rate_limited = response("429 Too Many Requests", "text/plain", "slow down");
+        let (base_url, request_log) = serve(vec![
+            rate_limited.clone(),
+            rate_limited.clone(),
+            rate_limited.clone(),
+            rate_limited.clone(),
+            rate_limited.clone(),
+            rate_limited,
+            response("200 OK", "text/event-stream", HELLO_STREAM),
+        ])
+        .await;
+        let config = config(
+            &base_url,
+            vec!["backup".to_string(), "last-resort".to_string()],
+        );
+
+        let message = stream(&config).await.unwrap().unwrap();
+
+        assert_eq!(message.content, "Hello");
+        let models: Vec<String> = request_log
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let body = request.split_once("\r\n\r\n").unwrap().1;
+                let body: serde_json::Value = serde_json::from_str(body).unwrap();
+                body["model"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            models,
+            [
+                "primary",
+                "primary",
+                "primary",
+                "backup",
+                "backup",
+                "backup",
+                "last-resort"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        let (base_url, request_log) = serve(vec![
+            response("400 Bad Request", "text/plain", "invalid model"),
+            response("200 OK", "text/event-stream", HELLO_STREAM),
+        ])
+        .await;
+
+        let error = stream(&config(&base_url, vec!["backup".to_string()]))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "API request failed with status 400 Bad Request: invalid model"
+        );
+        assert_eq!(request_log.lock().unwrap().len(), 1);
+    }
+}
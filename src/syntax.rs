@@ -0,0 +1,572 @@
+//! # Syntax Trees
+//!
+//! This module wraps tree-sitter to give the tools a structural view of source files.
+//! It detects the language of a file, parses it, and extracts a nested outline of
+//! its items (modules, types, impls, functions), each with its 1-indexed line span.
+
+use anyhow::{Result, anyhow};
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser, Tree};
+
+/// The languages with tree-sitter support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxLanguage {
+    Rust,
+    TypeScript,
+    Tsx,
+    Python,
+    Go,
+}
+
+impl SyntaxLanguage {
+    /// Detects the language from a file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Self::Rust),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "py" | "pyi" => Some(Self::Python),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    fn tree_sitter_language(self) -> Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    /// Returns the outline kind for an item node, or `None` if the node is not an outline item.
+    fn item_kind(self, node: &Node) -> Option<&'static str> {
+        let kind = match (self, node.kind()) {
+            (Self::Rust, "mod_item") => "mod",
+            (Self::Rust, "struct_item") => "struct",
+            (Self::Rust, "enum_item") => "enum",
+            (Self::Rust, "union_item") => "union",
+            (Self::Rust, "trait_item") => "trait",
+            (Self::Rust, "impl_item") => "impl",
+            (Self::Rust, "function_item" | "function_signature_item") => "fn",
+            (Self::Rust, "const_item") => "const",
+            (Self::Rust, "static_item") => "static",
+            (Self::Rust, "type_item") => "type",
+            (Self::Rust, "macro_definition") => "macro",
+            (Self::TypeScript | Self::Tsx, "class_declaration" | "abstract_class_declaration") => {
+                "class"
+            }
+            (Self::TypeScript | Self::Tsx, "interface_declaration") => "interface",
+            (Self::TypeScript | Self::Tsx, "enum_declaration") => "enum",
+            (Self::TypeScript | Self::Tsx, "type_alias_declaration") => "type",
+            (Self::TypeScript | Self::Tsx, "internal_module" | "module") => "namespace",
+            (
+                Self::TypeScript | Self::Tsx,
+                "function_declaration" | "generator_function_declaration",
+            ) => "function",
+            (Self::TypeScript | Self::Tsx, "method_definition" | "method_signature") => "method",
+            // `const handler = () => {}` is a function in all but name.
+            (Self::TypeScript | Self::Tsx, "variable_declarator")
+                if node.child_by_field_name("value").is_some_and(|value| {
+                    matches!(value.kind(), "arrow_function" | "function_expression")
+                }) =>
+            {
+                "function"
+            }
+            (Self::Python, "class_definition") => "class",
+            (Self::Python, "function_definition") => "def",
+            (Self::Go, "function_declaration") => "func",
+            (Self::Go, "method_declaration") => "method",
+            (Self::Go, "type_spec") => "type",
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// An item in a file's outline, e.g. a function or a struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutlineItem {
+    /// A short, language-specific kind like `fn`, `impl`, `class` or `def`.
+    pub kind: &'static str,
+    pub name: String,
+    /// The 1-indexed first line of the item, including attached attributes or doc comments.
+    pub start_line: usize,
+    /// The 1-indexed, inclusive last line of the item.
+    pub end_line: usize,
+    pub children: Vec<OutlineItem>,
+}
+
+/// Parses `source` with the grammar of `language`.
+pub fn parse(language: SyntaxLanguage, source: &str) -> Result<Tree> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language.tree_sitter_language())
+        .map_err(|e| anyhow!("Failed to load the {language:?} grammar: {e}"))?;
+    parser
+        .parse(source, None)
+        .ok_or_else(|| anyhow!("Failed to parse the file as {language:?}"))
+}
+
+/// Extracts the nested outline of a source file.
+pub fn outline(language: SyntaxLanguage, source: &str) -> Result<Vec<OutlineItem>> {
+    let tree = parse(language, source)?;
+    Ok(collect_items(language, tree.root_node(), source.as_bytes()))
+}
+
+/// A location where the parser could not make sense of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// The 1-indexed line of the error.
+    pub line: usize,
+    /// The 1-indexed column of the error.
+    pub column: usize,
+    pub message: String,
+}
+
+/// Collects the parse errors in `source`, in document order.
+pub fn syntax_errors(language: SyntaxLanguage, source: &str) -> Result<Vec<SyntaxError>> {
+    let tree = parse(language, source)?;
+    let mut errors = Vec::new();
+    if tree.root_node().has_error() {
+        collect_errors(tree.root_node(), source.as_bytes(), &mut errors);
+    }
+    Ok(errors)
+}
+
+fn collect_errors(node: Node, source: &[u8], errors: &mut Vec<SyntaxError>) {
+    if node.is_missing() || node.is_error() {
+        let position = node.start_position();
+        let message = if node.is_missing() {
+            format!("missing `{}`", node.kind())
+        } else {
+            let text = node_text(&node, source);
+            let first_line = text.lines().next().unwrap_or_default().trim();
+            format!("unexpected `{first_line}`")
+        };
+        errors.push(SyntaxError {
+            line: position.row + 1,
+            column: position.column + 1,
+            message,
+        });
+        return;
+    }
+    if !node.has_error() {
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_errors(child, source, errors);
+    }
+}
+
+/// Finds the item with the given qualified path, e.g. `impl Agent::prepare_request`.
+///
+/// Each segment matches an item by name (`prepare_request`) or by kind and name
+/// (`fn prepare_request`). Segments are separated by `::` or `.`, and the path may
+/// start at any nesting level, so a bare name is enough when it is unique.
+pub fn find_symbol<'a>(items: &'a [OutlineItem], path: &str) -> Result<&'a OutlineItem> {
+    let path = path.trim();
+    let mut matches = Vec::new();
+    collect_symbol_matches(items, path, "", &mut matches);
+    match matches.as_slice() {
+        [(_, item)] => Ok(item),
+        [] => Err(anyhow!(
+            "Symbol '{path}' not found. Use `file_outline` to list the available symbols."
+        )),
+        _ => Err(anyhow!(
+            "Symbol '{path}' is ambiguous. Candidates:\n{}",
+            matches
+                .iter()
+                .map(|(qualified, item)| format!(
+                    "- {qualified} (lines {}-{})",
+                    item.start_line, item.end_line
+                ))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )),
+    }
+}
+
+/// Collects all items matching `path`, starting the match at this level and at every level below.
+fn collect_symbol_matches<'a>(
+    items: &'a [OutlineItem],
+    path: &str,
+    parent: &str,
+    matches: &mut Vec<(String, &'a OutlineItem)>,
+) {
+    for item in items {
+        let qualified = if parent.is_empty() {
+            format!("{} {}", item.kind, item.name)
+        } else {
+            format!("{parent}::{} {}", item.kind, item.name)
+        };
+        match_symbol_path(item, path, &qualified, matches);
+        collect_symbol_matches(&item.children, path, &qualified, matches);
+    }
+}
+
+/// Matches `path` against `item`, descending into its children for the remaining segments.
+fn match_symbol_path<'a>(
+    item: &'a OutlineItem,
+    path: &str,
+    qualified: &str,
+    matches: &mut Vec<(String, &'a OutlineItem)>,
+) {
+    for label in [item.name.clone(), format!("{} {}", item.kind, item.name)] {
+        if path == label {
+            matches.push((qualified.to_string(), item));
+            return;
+        }
+        let rest = path
+            .strip_prefix(&label)
+            .and_then(|rest| rest.strip_prefix("::").or_else(|| rest.strip_prefix('.')));
+        if let Some(rest) = rest {
+            for child in &item.children {
+                let child_qualified = format!("{qualified}::{} {}", child.kind, child.name);
+                match_symbol_path(child, rest, &child_qualified, matches);
+            }
+            return;
+        }
+    }
+}
+
+fn collect_items(language: SyntaxLanguage, node: Node, source: &[u8]) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match language.item_kind(&child) {
+            Some(kind) => {
+                let span_node = declaration_node(language, child);
+                items.push(OutlineItem {
+                    kind,
+                    name: item_name(language, &child, source),
+                    start_line: leading_comments_start(span_node).start_position().row + 1,
+                    end_line: end_line(&span_node),
+                    children: collect_items(language, child, source),
+                });
+            }
+            None => items.extend(collect_items(language, child, source)),
+        }
+    }
+    items
+}
+
+/// Returns the node whose span covers the whole declaration of an item.
+/// E.g. a TypeScript `variable_declarator` is only part of its `const` statement,
+/// and Python decorators belong to the decorated function.
+fn declaration_node(language: SyntaxLanguage, node: Node) -> Node {
+    let Some(parent) = node.parent() else {
+        return node;
+    };
+    let wraps = match language {
+        SyntaxLanguage::TypeScript | SyntaxLanguage::Tsx => {
+            node.kind() == "variable_declarator"
+                && matches!(
+                    parent.kind(),
+                    "lexical_declaration" | "variable_declaration"
+                )
+                && parent.named_child_count() == 1
+        }
+        SyntaxLanguage::Python => parent.kind() == "decorated_definition",
+        SyntaxLanguage::Go => node.kind() == "type_spec" && parent.named_child_count() == 1,
+        SyntaxLanguage::Rust => false,
+    };
+    if !wraps {
+        return node;
+    }
+    // An exported TypeScript declaration is wrapped once more.
+    match parent.parent() {
+        Some(grandparent) if grandparent.kind() == "export_statement" => grandparent,
+        _ => parent,
+    }
+}
+
+/// Extends an item's span upwards over directly preceding attributes and doc comments.
+fn leading_comments_start(node: Node) -> Node {
+    let mut start = node;
+    while let Some(previous) = start.prev_sibling() {
+        let is_attachment = matches!(
+            previous.kind(),
+            "attribute_item" | "line_comment" | "block_comment" | "comment" | "decorator"
+        );
+        // Only attach comments that directly touch the item, without a blank line in between.
+        if !is_attachment || previous.end_position().row + 1 < start.start_position().row {
+            break;
+        }
+        start = previous;
+    }
+    start
+}
+
+fn end_line(node: &Node) -> usize {
+    let end = node.end_position();
+    // A node that ends at column 0 ends with the newline of the previous line.
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row
+    } else {
+        end.row + 1
+    }
+}
+
+fn node_text(node: &Node, source: &[u8]) -> String {
+    node.utf8_text(source).unwrap_or_default().to_string()
+}
+
+fn item_name(language: SyntaxLanguage, node: &Node, source: &[u8]) -> String {
+    match (language, node.kind()) {
+        (SyntaxLanguage::Rust, "impl_item") => {
+            let type_name = node
+                .child_by_field_name("type")
+                .map(|n| node_text(&n, source))
+                .unwrap_or_default();
+            match node.child_by_field_name("trait") {
+                Some(trait_node) => format!("{} for {type_name}", node_text(&trait_node, source)),
+                None => type_name,
+            }
+        }
+        (SyntaxLanguage::Go, "method_declaration") => {
+            let name = node
+                .child_by_field_name("name")
+                .map(|n| node_text(&n, source))
+                .unwrap_or_default();
+            match node
+                .child_by_field_name("receiver")
+                .and_then(|receiver| find_descendant(receiver, "type_identifier"))
+            {
+                Some(receiver_type) => format!("{}.{name}", node_text(&receiver_type, source)),
+                None => name,
+            }
+        }
+        _ => node
+            .child_by_field_name("name")
+            .map(|n| node_text(&n, source))
+            .unwrap_or_else(|| "<anonymous>".to_string()),
+    }
+}
+
+fn find_descendant<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    if node.kind() == kind {
+        return Some(node);
+    }
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .find_map(|child| find_descendant(child, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarize(items: &[OutlineItem]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for item in items {
+            lines.push(format!(
+                "{} {} {}-{}",
+                item.kind, item.name, item.start_line, item.end_line
+            ));
+            for child in summarize(&item.children) {
+                lines.push(format!("  {child}"));
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn test_rust_outline() {
+        let source = "\
+use std::fmt;
+
+/// An agent.
+#[derive(Debug)]
+pub struct Agent {
+    name: String,
+}
+
+impl Agent {
+    pub fn new() -> Self {
+        todo!()
+    }
+
+    fn helper(&self) {}
+}
+
+impl fmt::Display for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
+mod tests {
+    fn it_works() {}
+}
+";
+        let items = outline(SyntaxLanguage::Rust, source).unwrap();
+        assert_eq!(
+            summarize(&items),
+            vec![
+                "struct Agent 3-7",
+                "impl Agent 9-15",
+                "  fn new 10-12",
+                "  fn helper 14-14",
+                "impl fmt::Display for Agent 17-21",
+                "  fn fmt 18-20",
+                "mod tests 23-25",
+                "  fn it_works 24-24",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_typescript_outline() {
+        let source = "\
+export class Service {
+  run(): void {}
+}
+
+interface Options {
+  verbose: boolean;
+}
+
+export const handler = async () => {
+  return 1;
+};
+
+function main() {}
+";
+        let items = outline(SyntaxLanguage::TypeScript, source).unwrap();
+        assert_eq!(
+            summarize(&items),
+            vec![
+                "class Service 1-3",
+                "  method run 2-2",
+                "interface Options 5-7",
+                "function handler 9-11",
+                "function main 13-13",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_python_outline() {
+        let source = "\
+class Greeter:
+    @staticmethod
+    def hello():
+        return 'hi'
+
+def main():
+    pass
+";
+        let items = outline(SyntaxLanguage::Python, source).unwrap();
+        assert_eq!(
+            summarize(&items),
+            vec!["class Greeter 1-4", "  def hello 2-4", "def main 6-7"]
+        );
+    }
+
+    #[test]
+    fn test_go_outline() {
+        let source = "\
+package main
+
+type Server struct {
+	port int
+}
+
+func (s *Server) Start() error {
+	return nil
+}
+
+func main() {}
+";
+        let items = outline(SyntaxLanguage::Go, source).unwrap();
+        assert_eq!(
+            summarize(&items),
+            vec![
+                "type Server 3-5",
+                "method Server.Start 7-9",
+                "func main 11-11"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(
+            syntax_errors(SyntaxLanguage::Rust, "fn main() {}\n")
+                .unwrap()
+                .is_empty()
+        );
+
+        let errors =
+            syntax_errors(SyntaxLanguage::Rust, "fn main() {\n    let x = ;\n}\n").unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+
+        let errors = syntax_errors(SyntaxLanguage::Python, "def f(:\n    pass\n").unwrap();
+        assert!(!errors.is_empty());
+        assert_eq!(errors[0].line, 1);
+    }
+
+    #[test]
+    fn test_find_symbol() {
+        let source = "\
+struct Agent;
+
+impl Agent {
+    fn new() -> Self { Agent }
+    fn prepare_request(&self) {}
+}
+
+impl Default for Agent {
+    fn default() -> Self { Agent::new() }
+}
+
+struct Other;
+
+impl Other {
+    fn new() -> Self { Other }
+}
+";
+        let items = outline(SyntaxLanguage::Rust, source).unwrap();
+        let lines = |path: &str| {
+            find_symbol(&items, path).map(|item| (item.kind, item.start_line, item.end_line))
+        };
+
+        assert_eq!(lines("impl Agent::prepare_request").unwrap(), ("fn", 5, 5));
+        assert_eq!(lines("Agent::fn prepare_request").unwrap(), ("fn", 5, 5));
+        assert_eq!(lines("prepare_request").unwrap(), ("fn", 5, 5));
+        assert_eq!(
+            lines("impl Default for Agent::default").unwrap(),
+            ("fn", 9, 9)
+        );
+        assert_eq!(lines("struct Other").unwrap(), ("struct", 12, 12));
+        assert_eq!(lines("Other.new").unwrap(), ("fn", 15, 15));
+
+        let ambiguous = lines("new").unwrap_err().to_string();
+        assert!(ambiguous.contains("- impl Agent::fn new (lines 4-4)"));
+        assert!(ambiguous.contains("- impl Other::fn new (lines 15-15)"));
+        assert!(
+            lines("missing")
+                .unwrap_err()
+                .to_string()
+                .contains("not found")
+        );
+    }
+
+    #[test]
+    fn test_language_detection() {
+        assert_eq!(
+            SyntaxLanguage::from_path(Path::new("src/main.rs")),
+            Some(SyntaxLanguage::Rust)
+        );
+        assert_eq!(
+            SyntaxLanguage::from_path(Path::new("app.tsx")),
+            Some(SyntaxLanguage::Tsx)
+        );
+        assert_eq!(SyntaxLanguage::from_path(Path::new("README.md")), None);
+    }
+}
@@ -0,0 +1,311 @@
+//! # System Prompt Templates
+//!
+//! The system prompt is a template, read from a template file or from the `system_prompt`
+//! setting. These placeholders are replaced with facts about the environment:
+//!
+//! - `{{cwd}}`: the working directory
+//! - `{{os}}`: the operating system, e.g. `linux`
+//! - `{{date}}`: today's date as `YYYY-MM-DD`
+//! - `{{git_branch}}`: the checked out git branch, empty outside of a repository
+//! - `{{tools}}`: the names of the available tools
+//! - `{{project_files}}`: the files in the working directory, respecting ignore rules
+//!
+//! Unknown placeholders are kept as they are.
+//!
+//! The template file is `system_prompt_file`, or `system_prompt.md` if it is not set. A
+//! relative path is looked up in the `.alors` directory of the project, then in the alors
+//! directory of the XDG config dir. A template file is rendered again when it changes,
+//! before the next request.
+
+use crate::checkpoint::git;
+use crate::config::Config;
+use crate::path_expander;
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The template file looked up when `system_prompt_file` is not set.
+pub const DEFAULT_TEMPLATE_FILE: &str = "system_prompt.md";
+
+/// The maximum number of files listed by `{{project_files}}`.
+const MAX_PROJECT_FILES: usize = 300;
+
+static PLACEHOLDER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*([A-Za-z_]+)\s*\}\}").unwrap());
+
+/// A system prompt template with the state needed to render it.
+#[derive(Debug)]
+pub struct SystemPromptTemplate {
+    template: String,
+    /// The template file, or `None` if the template is the `system_prompt` setting.
+    path: Option<PathBuf>,
+    /// The modification time of the template file when it was read.
+    modified: Option<SystemTime>,
+    tool_names: Vec<String>,
+}
+
+impl SystemPromptTemplate {
+    /// Loads the template for the configuration. Returns `None` if the system prompt is
+    /// disabled with an empty `system_prompt`.
+    pub fn load(config: &Config, dir: &Path, tool_names: Vec<String>) -> Result<Option<Self>> {
+        let Some(system_prompt) = &config.system_prompt else {
+            return Ok(None);
+        };
+        let mut template = Self {
+            template: system_prompt.clone(),
+            path: find_template_file(config, dir)?,
+            modified: None,
+            tool_names,
+        };
+        if template.path.is_some() {
+            template.reload_if_changed()?;
+        }
+        Ok(Some(template))
+    }
+
+    /// The template file, if the template is not the `system_prompt` setting.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Reads the template file again if it was modified since it was last read.
+    /// Returns whether the template changed.
+    pub fn reload_if_changed(&mut self) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| anyhow!("Failed to read system prompt {}: {e}", path.display()))?;
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        let template = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read system prompt {}: {e}", path.display()))?;
+        self.modified = Some(modified);
+        let changed = template != self.template;
+        self.template = template;
+        Ok(changed)
+    }
+
+    /// Replaces the placeholders with the values for the working directory `dir`.
+    pub fn render(&self, config: &Config, dir: &Path) -> String {
+        PLACEHOLDER_RE
+            .replace_all(&self.template, |captures: &regex::Captures| {
+                self.variable(&captures[1], config, dir)
+                    .unwrap_or_else(|| captures[0].to_string())
+            })
+            .to_string()
+    }
+
+    /// Returns the value of a variable, or `None` if there is no such variable.
+    /// Values are computed only when they are used, since some need a walk or a subprocess.
+    fn variable(&self, name: &str, config: &Config, dir: &Path) -> Option<String> {
+        let value = match name {
+            "cwd" => dir.display().to_string(),
+            "os" => std::env::consts::OS.to_string(),
+            "date" => today(),
+            "git_branch" => git(dir, &["rev-parse", "--abbrev-ref", "HEAD"], None)
+                .map(|branch| branch.trim().to_string())
+                .unwrap_or_default(),
+            "tools" => self.tool_names.join(", "),
+            "project_files" => project_files(config, dir),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Finds the template file. An explicitly configured file must exist, the default one
+/// is optional.
+fn find_template_file(config: &Config, dir: &Path) -> Result<Option<PathBuf>> {
+    let name = config
+        .system_prompt_file
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE_FILE);
+    let path = Path::new(name);
+    let found = if path.is_absolute() {
+        Some(path.to_path_buf()).filter(|path| path.is_file())
+    } else {
+        dir.ancestors()
+            .map(|ancestor| ancestor.join(".alors"))
+            .find(|alors_dir| alors_dir.is_dir())
+            .map(|alors_dir| alors_dir.join(path))
+            .filter(|path| path.is_file())
+            .or_else(|| xdg::BaseDirectories::new().find_config_file(Path::new("alors").join(path)))
+    };
+    match (found, &config.system_prompt_file) {
+        (None, Some(name)) => Err(anyhow!(
+            "System prompt file '{name}' not found. Relative paths are looked up in the .alors directory of the project and in ~/.config/alors."
+        )),
+        (found, _) => Ok(found),
+    }
+}
+
+/// Lists the files in `dir` relative to it, one per line.
+fn project_files(config: &Config, dir: &Path) -> String {
+    let files = path_expander::expand_and_validate(
+        &[dir.to_string_lossy().to_string()],
+        &config.ignored_paths,
+    )
+    .files;
+    let mut lines: Vec<String> = files
+        .iter()
+        .take(MAX_PROJECT_FILES)
+        .map(|file| {
+            Path::new(file)
+                .strip_prefix(dir)
+                .unwrap_or(Path::new(file))
+                .display()
+                .to_string()
+        })
+        .collect();
+    if files.len() > MAX_PROJECT_FILES {
+        lines.push(format!(
+            "[... {} more files]",
+            files.len() - MAX_PROJECT_FILES
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar.
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigLayer;
+    use tempfile::Builder;
+
+    fn config(system_prompt_file: Option<&str>) -> Config {
+        let mut config = Config::default();
+        config.merge(&ConfigLayer {
+            system_prompt_file: system_prompt_file.map(str::to_string),
+            ..Default::default()
+        });
+        config
+    }
+
+    #[test]
+    fn test_render_variables() {
+        let tmp_dir = Builder::new()
+            .prefix("test-system-prompt")
+            .tempdir()
+            .unwrap();
+        let dir = tmp_dir.path();
+        fs::create_dir(dir.join(".alors")).unwrap();
+        fs::write(
+            dir.join(".alors/system_prompt.md"),
+            "In {{ cwd }} on {{os}}.\nTools: {{tools}}\nFiles:\n{{project_files}}\nKept: {{unknown}}",
+        )
+        .unwrap();
+        fs::write(dir.join("main.rs"), "").unwrap();
+
+        let template = SystemPromptTemplate::load(
+            &config(None),
+            dir,
+            vec!["read_files".to_string(), "edit_files".to_string()],
+        )
+        .unwrap()
+        .unwrap();
+        let rendered = template.render(&config(None), dir);
+
+        assert_eq!(
+            template.path(),
+            Some(dir.join(".alors/system_prompt.md").as_path())
+        );
+        assert_eq!(
+            rendered,
+            format!(
+                "In {} on {}.\nTools: read_files, edit_files\nFiles:\n.alors/system_prompt.md\nmain.rs\nKept: {{{{unknown}}}}",
+                dir.display(),
+                std::env::consts::OS
+            )
+        );
+    }
+
+    #[test]
+    fn test_inline_template_and_missing_file() {
+        let tmp_dir = Builder::new()
+            .prefix("test-system-prompt")
+            .tempdir()
+            .unwrap();
+        let dir = tmp_dir.path();
+        let mut inline = config(None);
+        inline.system_prompt = Some("Branch: '{{git_branch}}'".to_string());
+
+        let template = SystemPromptTemplate::load(&inline, dir, vec![])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(template.path(), None);
+        assert_eq!(template.render(&inline, dir), "Branch: ''");
+        let error =
+            SystemPromptTemplate::load(&config(Some("missing.md")), dir, vec![]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("System prompt file 'missing.md' not found.")
+        );
+    }
+
+    #[test]
+    fn test_reload_if_changed() {
+        let tmp_dir = Builder::new()
+            .prefix("test-system-prompt")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("prompt.md");
+        fs::write(&path, "first").unwrap();
+        let config = config(Some(&path.to_string_lossy()));
+        let mut template = SystemPromptTemplate::load(&config, tmp_dir.path(), vec![])
+            .unwrap()
+            .unwrap();
+        assert!(!template.reload_if_changed().unwrap());
+
+        fs::write(&path, "second {{os}}").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(10))
+            .unwrap();
+
+        assert!(template.reload_if_changed().unwrap());
+        assert_eq!(
+            template.render(&config, tmp_dir.path()),
+            format!("second {}", std::env::consts::OS)
+        );
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_744), (2026, 10, 18));
+    }
+}
@@ -4,7 +4,7 @@
 //! It maintains a registry of all available tools and dispatches calls to the appropriate
 //! implementation based on the tool name.
 
-use crate::{config::Config, file_state_manager::FileStateManager, tools::Tool};
+use crate::{config::Config, file_state_manager::FileStateManager, permissions, tools::Tool};
 use anyhow::{Result, anyhow};
 use console::style;
 use openrouter_api::{
@@ -88,13 +88,14 @@ impl ToolCollection {
     /// This function is designed to always succeed from the caller's perspective,
     /// returning a `Message`. Any failures in tool lookup, argument parsing,
     /// or execution are captured and returned within the `content` of the
-    /// `tool` role message.
+    /// `tool` role message. Known secret values are redacted from the content.
+    /// Also returns whether the tool succeeded.
     pub async fn execute_tool_call(
         &self,
         tool_call: &ToolCall,
         config: &Config,
         fsm: Arc<Mutex<FileStateManager>>,
-    ) -> Message {
+    ) -> (Message, bool) {
         let function_name = &tool_call.function_call.name;
         let arguments = &tool_call.function_call.arguments;
 
@@ -111,6 +112,7 @@ impl ToolCollection {
         }
         .await;
 
+        let succeeded = result.is_ok();
         let message_content = match result {
             Ok(output) => {
                 if config.debug_tool_calls {
@@ -135,13 +137,15 @@ impl ToolCollection {
             }
         };
 
-        Message {
+        let message = Message {
             role: "tool".to_string(),
-            content: message_content,
+            // Secrets must never be sent to the LLM, no matter which tool leaked them.
+            content: permissions::redact_secrets(&message_content, config),
             name: Some(function_name.to_string()),
             tool_call_id: Some(tool_call.id.clone()),
             tool_calls: None,
-        }
+        };
+        (message, succeeded)
     }
 }
 
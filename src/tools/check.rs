@@ -0,0 +1,201 @@
+//! # Check Tool
+//!
+//! This module provides the `check` tool, which runs the configured build command
+//! (e.g. `cargo check`) and returns its diagnostics grouped by file, with the affected
+//! lines already rendered in LIF form.
+
+use crate::config::Config;
+use crate::diagnostics::{self, Diagnostic};
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::tools::Tool;
+use crate::tools::execute_shell_command::{capture_shell_command, plan_workdir};
+use anyhow::Result;
+use async_trait::async_trait;
+use console::style;
+use openrouter_api::models::tool::FunctionDescription;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::NoneAsEmptyString;
+use serde_with::serde_as;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[serde_as]
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CheckArgs {
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub workdir: Option<String>,
+}
+
+pub struct CheckTool;
+
+#[async_trait]
+impl Tool for CheckTool {
+    fn name(&self) -> &'static str {
+        "check"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "check".to_string(),
+            description: Some(
+                "Runs the project's configured build check (e.g. `cargo check`) and returns its errors and warnings grouped by file.
+Each diagnostic includes the LID of its line, followed by the affected lines in LIF form. Use these LIDs directly with `edit_files`, there is no need to read the files again.
+Prefer this tool over running the compiler through `execute_shell_command`."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "workdir": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The directory to run the check in. Defaults to the current working directory."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["workdir"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: CheckArgs = serde_json::from_value(args.clone())?;
+        plan_workdir(args.workdir.as_deref(), config)?;
+        let mut output = vec![];
+        if let Some(workdir) = &args.workdir {
+            output.push(format!("Workdir: {workdir}"));
+        }
+        output.push(format!("$ {}", style(&config.check_command).bold()));
+        Ok(output.join("\n"))
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: CheckArgs = serde_json::from_value(args.clone())?;
+        plan_workdir(args.workdir.as_deref(), config)?;
+        execute_check(&args, config, fsm).await
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        // The command itself comes from the user's configuration, so only the location matters.
+        let args: CheckArgs = serde_json::from_value(args.clone())?;
+        Ok(plan_workdir(args.workdir.as_deref(), config).is_ok())
+    }
+}
+
+/// Parses diagnostics from a build command's output.
+/// Cargo's JSON messages are preferred; otherwise generic `file:line:col` lines are used.
+pub fn parse_diagnostics(stdout: &str, stderr: &str) -> Vec<Diagnostic> {
+    let diagnostics = diagnostics::parse_cargo_json(stdout);
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+    let mut diagnostics = diagnostics::parse_generic(stdout);
+    diagnostics.extend(diagnostics::parse_generic(stderr));
+    diagnostics
+}
+
+pub async fn execute_check(
+    args: &CheckArgs,
+    config: &Config,
+    fsm: Arc<Mutex<FileStateManager>>,
+) -> Result<String> {
+    let command = &config.check_command;
+    println!("$ {}", style(command).bold());
+    let env = permissions::command_env(config);
+    let result = capture_shell_command(command, args.workdir.as_deref(), &env).await?;
+
+    let diagnostics = parse_diagnostics(&result.stdout, &result.stderr);
+    let summary = format!(
+        "{} | {}",
+        result.exit_message(),
+        diagnostics::summarize(&diagnostics)
+    );
+    println!("{}", style(&summary).bold());
+
+    let mut output = vec![format!("$ {command}"), summary];
+    if !diagnostics.is_empty() {
+        let base_dir = Path::new(args.workdir.as_deref().unwrap_or("."));
+        let mut manager = fsm.lock().unwrap();
+        output.push(String::new());
+        output.push(diagnostics::render_diagnostics(
+            &diagnostics,
+            base_dir,
+            config,
+            &mut manager,
+        ));
+    } else if result.exit_code != Some(0) {
+        // Without diagnostics, the raw output is the only hint at what went wrong.
+        output.push(String::new());
+        output.push(result.output_tail());
+    }
+    Ok(output.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    #[tokio::test]
+    async fn test_check_reports_diagnostics_with_lids() {
+        let tmp_dir = Builder::new().prefix("test-check-").tempdir().unwrap();
+        fs::write(tmp_dir.path().join("main.c"), "int main() {\n  foo();\n}\n").unwrap();
+        let workdir = tmp_dir.path().to_str().unwrap().to_string();
+        let config = Config {
+            accessible_paths: vec![workdir.clone()],
+            check_command:
+                "echo \"main.c:2:3: error: implicit declaration of function 'foo'\" >&2; exit 1"
+                    .to_string(),
+            ..Default::default()
+        };
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let args = CheckArgs {
+            workdir: Some(workdir.clone()),
+        };
+
+        let output = execute_check(&args, &config, fsm.clone()).await.unwrap();
+
+        let lid = fsm
+            .lock()
+            .unwrap()
+            .open_file(tmp_dir.path().join("main.c").to_str().unwrap())
+            .unwrap()
+            .lid_at_line(2)
+            .unwrap();
+        assert!(output.contains("Exit code: 1 | 1 error, 0 warnings"));
+        assert!(output.contains(&format!(
+            "  error 2:3 {lid}: implicit declaration of function 'foo'"
+        )));
+        assert!(output.contains(&format!("2    {lid}:   foo();")));
+    }
+
+    #[tokio::test]
+    async fn test_check_without_diagnostics_shows_output_tail() {
+        let config = Config {
+            check_command: "echo 'linker failed'; exit 2".to_string(),
+            ..Default::default()
+        };
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let args = CheckArgs { workdir: None };
+
+        let output = execute_check(&args, &config, fsm).await.unwrap();
+
+        assert!(output.contains("Exit code: 2 | 0 errors, 0 warnings"));
+        assert!(output.ends_with("linker failed"));
+    }
+}
@@ -192,6 +192,7 @@ pub fn execute_create_files(
     }
 
     let mut results = Vec::new();
+    let mut any_created = false;
     for spec in &args.files {
         let result: Result<String> = (|| {
             // All validation (existence, permissions) is now done in the planner.
@@ -209,7 +210,10 @@ pub fn execute_create_files(
         })();
 
         match result {
-            Ok(success_msg) => results.push(success_msg),
+            Ok(success_msg) => {
+                any_created = true;
+                results.push(success_msg);
+            }
             Err(e) => {
                 // Execution-time errors are still possible (e.g., disk full, permissions changed).
                 // We wrap them in a consistent format.
@@ -217,6 +221,10 @@ pub fn execute_create_files(
             }
         }
     }
+    // If no file was created, the tool call failed as a whole.
+    if !any_created {
+        return Err(anyhow!(results.join("\n\n---\n\n")));
+    }
     Ok(results.join("\n\n---\n\n"))
 }
 
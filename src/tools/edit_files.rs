@@ -4,7 +4,7 @@
 //!
 //! 1.  **Schema Definition**: Defines the JSON schema for the `edit_file` tool. This schema
 //!     is sent to the LLM, instructing it on how to format its edit requests. It uses
-//!     dedicated "buckets" (`inserts`, `replaces`, etc.) for clarity.
+//!     dedicated "buckets" (`inserts`, `replaces`, `replace_symbols`, etc.) for clarity.
 //!
 //! 2.  **Request Handling & Validation**: Implements `execute_file_operations`, the function
 //!     that orchestrates the entire editing process. It receives a batch of requested
@@ -15,13 +15,20 @@
 //!
 //! 4.  **Translation**: Validated requests are translated into simple, internal `PatchOperation`
 //!     primitives, which are then passed to the `FileState` module for execution.
+//!
+//! 5.  **Post-Edit Checks**: Before writing, the patched content is optionally parsed with
+//!     tree-sitter to reject edits that introduce syntax errors (`validate_edit_syntax`), and
+//!     optionally run through a formatter.
 
-use crate::config::Config;
-use crate::file_state::FileState;
+use crate::config::{self, Config};
+use crate::file_state::{FileState, RangeSpec};
 use crate::file_state_manager::FileStateManager;
+use crate::lsp::LspManager;
 use crate::patch::{InsertOp, PatchOperation, ReplaceOp};
 use crate::permissions;
+use crate::syntax::{self, SyntaxLanguage};
 use crate::tools::Tool;
+use crate::tools::read_files::merge_ranges;
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use once_cell::sync::Lazy;
@@ -30,8 +37,12 @@ use regex::Regex;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
@@ -59,6 +70,8 @@ pub struct TopLevelRequest {
     pub replaces: Vec<ReplaceRequest>,
     #[serde(default, deserialize_with = "deserialize_null_default")]
     pub moves: Vec<MoveRequest>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    pub replace_symbols: Vec<ReplaceSymbolRequest>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq, Serialize)]
@@ -88,6 +101,15 @@ pub struct ReplaceRequest {
     pub new_content: String,
 }
 
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ReplaceSymbolRequest {
+    pub file_path: String,
+    /// A qualified symbol path like `impl Agent::prepare_request`, resolved through tree-sitter.
+    pub symbol: String,
+    pub new_content: String,
+}
+
 #[derive(Deserialize, Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct MoveRequest {
@@ -104,7 +126,19 @@ pub struct EditPlan {
     pub planned_ops: HashMap<PathBuf, Vec<PatchOperation>>,
 }
 
-pub struct FileEditorTool;
+#[derive(Default)]
+pub struct FileEditorTool {
+    /// Receives the new content of every written file, so language servers stay in sync.
+    lsp_manager: Option<Arc<Mutex<LspManager>>>,
+}
+
+impl FileEditorTool {
+    pub fn new(lsp_manager: Arc<Mutex<LspManager>>) -> Self {
+        Self {
+            lsp_manager: Some(lsp_manager),
+        }
+    }
+}
 
 #[async_trait]
 impl Tool for FileEditorTool {
@@ -122,12 +156,13 @@ impl Tool for FileEditorTool {
 
 - To replace the entire file, set both `anchor_range_begin` and `anchor_range_end` to `null`.
 
-**Execution Order**: 1. Moves, 2. Replaces, 3. Inserts.
+**Execution Order**: 1. Moves, 2. Replaces, 3. Symbol replaces, 4. Inserts.
 
 **Operations**:
 - `inserts`: Adds new lines, positioned relative to a context anchor.
 - `replaces`: Replaces a range of lines from `anchor_range_begin` to `anchor_range_end`, including the anchor lines themselves.
 - `moves`: Moves a range of lines from `source_range_start_anchor` to `source_range_end_anchor`, including the anchor lines themselves.
+- `replace_symbols`: Replaces a whole item (function, struct, impl, class, ...) by its qualified name, e.g. `impl Agent::prepare_request`, including its doc comments and attributes. Supported for Rust, TypeScript, Python and Go. Prefer this over `replaces` for replacing whole functions.
 "#
                     .to_string(),
             ),
@@ -224,6 +259,21 @@ impl Tool for FileEditorTool {
                             "required": ["file_path", "anchor_range_begin", "new_content", "anchor_range_end"]
                         }
                     },
+                    "replace_symbols": {
+                        "type": "array",
+                        "description": "A list of operations that replace a whole item, found by its qualified name.",
+                        "items": {
+                            "type": "object",
+                            "title": "Replace Symbol Operation",
+                            "properties": {
+                                "file_path": { "type": "string", "description": "The relative path to the file to be modified." },
+                                "symbol": { "type": "string", "description": "The qualified symbol path, as shown by `file_outline`. Segments are separated by `::` and may include the kind, e.g. `impl Agent::prepare_request`, `mod tests::fn it_works` or `Greeter.hello`. A bare name is enough if it is unique." },
+                                "new_content": { "type": "string", "description": "The complete new source of the item, including its doc comments and attributes. Use an empty string to delete the item." }
+                            },
+                            "additionalProperties": false,
+                            "required": ["file_path", "symbol", "new_content"]
+                        }
+                    },
                     "inserts": {
                         "type": "array",
                         "description": "A list of insert operations to perform.",
@@ -253,7 +303,7 @@ impl Tool for FileEditorTool {
                     }
                 },
                 "additionalProperties": false,
-                "required": ["moves", "replaces", "inserts"]
+                "required": ["moves", "replaces", "replace_symbols", "inserts"]
             }),
         }
     }
@@ -285,8 +335,14 @@ impl Tool for FileEditorTool {
         fsm: Arc<Mutex<FileStateManager>>,
     ) -> Result<String> {
         let args: TopLevelRequest = serde_json::from_value(args.clone())?;
-        let mut manager = fsm.lock().unwrap();
-        execute_file_operations(&args, &mut manager, &config.accessible_paths)
+        let config = config.clone();
+        let lsp_manager = self.lsp_manager.clone();
+        // Formatters run as subprocesses, so the edit must not block the async runtime.
+        tokio::task::spawn_blocking(move || {
+            let mut manager = fsm.lock().unwrap();
+            execute_file_operations(&args, &mut manager, &config, lsp_manager.as_deref())
+        })
+        .await?
     }
 
     fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
@@ -308,6 +364,14 @@ impl Tool for FileEditorTool {
             }
         }
 
+        for req in &args.replace_symbols {
+            if permissions::is_path_accessible(Path::new(&req.file_path), &config.accessible_paths)
+                .is_err()
+            {
+                return Ok(false);
+            }
+        }
+
         for req in &args.moves {
             if permissions::is_path_accessible(
                 Path::new(&req.source_file_path),
@@ -621,6 +685,61 @@ pub fn plan_file_operations(
         }
     }
 
+    // Plan Symbol Replaces
+    for (i, req) in args.replace_symbols.iter().enumerate() {
+        let result: Result<(PathBuf, PatchOperation)> = (|| {
+            permissions::is_path_accessible(Path::new(&req.file_path), accessible_paths)?;
+            let file_state = file_state_manager.open_file(&req.file_path)?;
+
+            let language = SyntaxLanguage::from_path(&file_state.path).ok_or_else(|| {
+                anyhow!("Symbol replacement is not supported for this file type.")
+            })?;
+            let items = syntax::outline(language, &file_state.get_full_content())?;
+            let item = syntax::find_symbol(&items, &req.symbol)?;
+
+            // The item's span is translated into LIDs, so the rest of the pipeline
+            // treats it exactly like an anchored `replace`.
+            let start_lid = file_state
+                .lines
+                .keys()
+                .nth(item.start_line - 1)
+                .cloned()
+                .ok_or_else(|| anyhow!("Symbol '{}' is out of range.", req.symbol))?;
+            let end_lid = file_state
+                .lines
+                .keys()
+                .nth(item.end_line - 1)
+                .cloned()
+                .ok_or_else(|| anyhow!("Symbol '{}' is out of range.", req.symbol))?;
+
+            let new_content_with_suffixes: Vec<(String, String)> = req
+                .new_content
+                .lines()
+                .map(|line| (line.to_string(), crate::file_state::generate_random_suffix()))
+                .collect();
+
+            Ok((
+                file_state.path.clone(),
+                PatchOperation::Replace(ReplaceOp {
+                    start_lid,
+                    end_lid,
+                    content: new_content_with_suffixes,
+                }),
+            ))
+        })();
+
+        match result {
+            Ok((path, op)) => planned_ops.entry(path).or_default().push(op),
+            Err(e) => {
+                validation_errors.push(anyhow!(
+                    "Replace symbol request #{i} (file: '{}', symbol: '{}'): {e}",
+                    req.file_path,
+                    req.symbol
+                ));
+            }
+        }
+    }
+
     // Plan Inserts
     for (i, req) in args.inserts.iter().enumerate() {
         let result: Result<(PathBuf, PatchOperation)> = (|| {
@@ -701,19 +820,26 @@ pub fn plan_file_operations(
     Ok(EditPlan { planned_ops })
 }
 
-/// The main execution function for the `edit_file` tool.
+/// The main execution function for the `edit_file` tool. Written files are sent to the
+/// language servers of `lsp_manager`, if given.
 pub fn execute_file_operations(
     args: &TopLevelRequest,
     file_state_manager: &mut FileStateManager,
-    accessible_paths: &[String],
+    config: &Config,
+    lsp_manager: Option<&Mutex<LspManager>>,
 ) -> Result<String> {
-    if args.inserts.is_empty() && args.replaces.is_empty() && args.moves.is_empty() {
+    if args.inserts.is_empty()
+        && args.replaces.is_empty()
+        && args.moves.is_empty()
+        && args.replace_symbols.is_empty()
+    {
         return Ok("No file operations provided in the tool call.".to_string());
     }
 
-    let plan = plan_file_operations(args, file_state_manager, accessible_paths)?;
+    let plan = plan_file_operations(args, file_state_manager, &config.accessible_paths)?;
 
     let mut results = Vec::new();
+    let mut any_applied = false;
 
     // --- Phase 2: Execute the consolidated plan ---
     for (path, operations) in plan.planned_ops {
@@ -722,30 +848,192 @@ pub fn execute_file_operations(
             let file_state = file_state_manager.get_file_state_mut(&file_path_str)?;
             let initial_hash = file_state.get_short_hash().to_string();
 
+            let (operations, formatter_note) = check_and_format(file_state, operations, config)?;
+
             // Apply all patches for this file at once
             let diff = file_state.apply_and_write_patch(&operations)?;
+            if let Some(lsp_manager) = lsp_manager {
+                lsp_manager
+                    .lock()
+                    .unwrap()
+                    .notify_file_changed(&file_state.path, &file_state.get_full_content());
+            }
             let new_short_hash = file_state.get_short_hash();
 
-            Ok(format!(
+            let mut message = format!(
                 "Patch from hash {initial_hash} applied successfully. New lif_hash: {new_short_hash}. Changes:\n{diff}"
-            ))
+            );
+            if let Some(note) = formatter_note {
+                message.push_str(&format!("\n{note}"));
+            }
+            Ok(message)
         })();
 
         match result {
-            Ok(success_msg) => results.push(format!("File: {file_path_str}\n{success_msg}")),
+            Ok(success_msg) => {
+                any_applied = true;
+                results.push(format!("File: {file_path_str}\n{success_msg}"));
+            }
             Err(e) => results.push(format!("File: {file_path_str}\nError: {e}")),
         }
     }
 
+    // If no file was changed, the tool call failed as a whole.
+    if !any_applied && !results.is_empty() {
+        return Err(anyhow!(results.join("\n\n---\n\n")));
+    }
     Ok(results.join("\n\n---\n\n"))
 }
 
+/// Runs the post-edit stage on the patched content of a file, before anything is written.
+///
+/// If syntax validation is enabled, the edit is rejected when it introduces new parse errors.
+/// If a formatter is configured for the file, its changes are appended to the patch, so
+/// unchanged lines keep their LIDs. A failing formatter does not block the edit, it only
+/// returns a note for the tool output.
+fn check_and_format(
+    file_state: &FileState,
+    mut operations: Vec<PatchOperation>,
+    config: &Config,
+) -> Result<(Vec<PatchOperation>, Option<String>)> {
+    let mut patched = file_state.clone();
+    patched.apply_patch(&operations)?;
+
+    if config.validate_edit_syntax
+        && let Some(language) = SyntaxLanguage::from_path(&file_state.path)
+    {
+        let errors_before = syntax::syntax_errors(language, &file_state.get_full_content())?;
+        let errors_after = syntax::syntax_errors(language, &patched.get_full_content())?;
+        if errors_after.len() > errors_before.len() {
+            return Err(anyhow!(render_syntax_errors(
+                &patched,
+                &errors_after,
+                errors_before.len()
+            )));
+        }
+    }
+
+    let Some(command) = config::command_for_extension(&config.formatters, &file_state.path) else {
+        return Ok((operations, None));
+    };
+    match run_formatter(command, &patched.get_full_content(), config) {
+        Ok(formatted) => {
+            operations.extend(patched.patch_to_content(&formatted));
+            Ok((operations, None))
+        }
+        Err(e) => Ok((
+            operations,
+            Some(format!(
+                "Note: formatter `{command}` failed, the edit was applied unformatted: {e}"
+            )),
+        )),
+    }
+}
+
+/// Explains a rejected edit, with the location of each syntax error in the rejected content.
+fn render_syntax_errors(
+    patched: &FileState,
+    errors: &[syntax::SyntaxError],
+    errors_before: usize,
+) -> String {
+    let mut lines = vec![format!(
+        "The edit was not applied because it introduces syntax errors ({errors_before} before, {} after):",
+        errors.len()
+    )];
+    for error in errors {
+        let lid = patched.lid_at_line(error.line).unwrap_or_default();
+        lines.push(format!(
+            "  {}:{} {lid}: {}",
+            error.line, error.column, error.message
+        ));
+    }
+
+    let total_lines = patched.lines.len();
+    let ranges: Vec<RangeSpec> = errors
+        .iter()
+        .map(|error| RangeSpec {
+            start_line: error.line.saturating_sub(2).max(1),
+            end_line: (error.line + 2).min(total_lines),
+        })
+        .collect();
+    lines.push("Rejected content:".to_string());
+    lines.push(patched.display_lif_contents_for_ranges(Some(&merge_ranges(ranges))));
+    lines.join("\n")
+}
+
+/// Pipes `content` through the formatter command and returns its output. The formatter is
+/// killed if it doesn't finish within `timeout_seconds`.
+fn run_formatter(command: &str, content: &str, config: &Config) -> Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env_clear()
+        .envs(permissions::command_env(config))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let (Some(mut stdin), Some(stdout), Some(stderr)) =
+        (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+        return Err(anyhow!("Failed to open the formatter's pipes"));
+    };
+    // The pipes are served from other threads, so a formatter that writes output before it
+    // has read all input can't block on a full pipe, and one that hangs can be killed.
+    let content = content.to_string();
+    let writer = thread::spawn(move || stdin.write_all(content.as_bytes()));
+    let stdout = read_pipe(stdout);
+    let stderr = read_pipe(stderr);
+
+    let deadline = Instant::now() + Duration::from_secs(config.timeout_seconds);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!(
+                "timed out after {} seconds",
+                config.timeout_seconds
+            ));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    join_pipe(writer)?;
+    let stdout = join_pipe(stdout)?;
+    let stderr = join_pipe(stderr)?;
+    if !status.success() {
+        return Err(anyhow!("{}", String::from_utf8_lossy(&stderr).trim()));
+    }
+    Ok(String::from_utf8(stdout)?)
+}
+
+/// Reads a pipe of the formatter to the end on another thread.
+fn read_pipe(mut pipe: impl Read + Send + 'static) -> JoinHandle<std::io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        pipe.read_to_end(&mut output).map(|_| output)
+    })
+}
+
+fn join_pipe<T>(handle: JoinHandle<std::io::Result<T>>) -> Result<T> {
+    handle
+        .join()
+        .map_err(|_| anyhow!("A formatter pipe thread panicked"))?
+        .map_err(Into::into)
+}
+
 fn create_diff_preview(
     args: &TopLevelRequest,
     file_state_manager: &mut FileStateManager,
     accessible_paths: &[String],
 ) -> Result<String> {
-    if args.inserts.is_empty() && args.replaces.is_empty() && args.moves.is_empty() {
+    if args.inserts.is_empty()
+        && args.replaces.is_empty()
+        && args.moves.is_empty()
+        && args.replace_symbols.is_empty()
+    {
         return Ok("No file edits will be performed.".to_string());
     }
 
@@ -2,12 +2,17 @@
 
 use super::{Anchor, FileEditorTool};
 use crate::{
-    config::Config, file_state::FileState, file_state_manager::FileStateManager, tools::Tool,
+    config::Config,
+    file_state::FileState,
+    file_state_manager::FileStateManager,
+    lsp::{self, LspManager},
+    tools::Tool,
 };
 use std::{
     fs,
     path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 use tempfile::Builder;
 
@@ -48,7 +53,7 @@ async fn test_execute_replace_successfully() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1\nline 2\nline 3\nline 4");
     let file_path_str = file_path.to_str().unwrap().to_string();
 
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     // Prime the FSM
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
@@ -79,7 +84,7 @@ async fn test_execute_delete_successfully() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1\nline 2\nline 3");
     let file_path_str = file_path.to_str().unwrap().to_string();
 
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
     let anchor_before = get_anchor(&state, 1); // line 2
@@ -106,7 +111,7 @@ async fn test_execute_insert_before_anchor() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1\nline 3");
     let file_path_str = file_path.to_str().unwrap().to_string();
 
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
     let anchor = get_anchor(&state, 1); // line 3
@@ -141,7 +146,7 @@ async fn test_execute_move_successfully() {
     fs::write(&dest_path, "dest line 1\ndest line 2").unwrap();
     let dest_path_str = dest_path.to_str().unwrap().to_string();
 
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     // Prime FSM
     fsm.lock().unwrap().open_file(&source_path_str).unwrap();
@@ -179,7 +184,7 @@ async fn test_execute_move_successfully() {
 async fn test_succeed_replace_with_no_anchors() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1");
     let file_path_str = file_path.to_str().unwrap().to_string();
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     let args = serde_json::json!({
         "replaces": [{
@@ -203,7 +208,7 @@ async fn test_succeed_replace_with_no_anchors() {
 async fn test_execute_fails_with_invalid_suffix() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1");
     let file_path_str = file_path.to_str().unwrap().to_string();
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
@@ -231,7 +236,7 @@ async fn test_execute_fails_with_invalid_suffix() {
 async fn test_insert_multiline_string() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1\nline 3");
     let file_path_str = file_path.to_str().unwrap().to_string();
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
@@ -257,7 +262,7 @@ async fn test_insert_multiline_string() {
 async fn test_replace_with_multiline_string() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("one\ntwo\nthree");
     let file_path_str = file_path.to_str().unwrap().to_string();
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
@@ -283,7 +288,7 @@ async fn test_replace_with_multiline_string() {
 async fn test_insert_with_trailing_newline() {
     let (_tmp_dir, file_path, fsm, config) = setup_fsm("line 1\nline 3");
     let file_path_str = file_path.to_str().unwrap().to_string();
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
@@ -310,7 +315,7 @@ async fn test_insert_empty_string_is_noop() {
     let original_content = "line 1\nline 2";
     let (_tmp_dir, file_path, fsm, config) = setup_fsm(original_content);
     let file_path_str = file_path.to_str().unwrap().to_string();
-    let tool = FileEditorTool;
+    let tool = FileEditorTool::default();
 
     fsm.lock().unwrap().open_file(&file_path_str).unwrap();
     let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
@@ -331,3 +336,192 @@ async fn test_insert_empty_string_is_noop() {
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, original_content);
 }
+
+#[tokio::test]
+async fn test_replace_symbol_successfully() {
+    let tmp_dir = Builder::new().prefix("test-fsm-").tempdir().unwrap();
+    let file_path = tmp_dir.path().join("lib.rs");
+    let original = "struct Agent;\n\nimpl Agent {\n    /// Builds the request.\n    fn prepare_request(&self) -> u8 {\n        1\n    }\n\n    fn other(&self) {}\n}\n";
+    fs::write(&file_path, original).unwrap();
+    let file_path_str = file_path.to_str().unwrap().to_string();
+    let config = Config {
+        accessible_paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+    let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+    let tool = FileEditorTool::default();
+
+    let args = serde_json::json!({
+        "replace_symbols": [{
+            "file_path": file_path_str,
+            "symbol": "impl Agent::prepare_request",
+            "new_content": "    fn prepare_request(&self) -> u8 {\n        2\n    }"
+        }],
+        "replaces": null,
+        "inserts": [],
+        "moves": []
+    });
+
+    let preview = tool.preview(&args, &config, fsm.clone()).unwrap();
+    assert!(preview.contains("/// Builds the request."));
+
+    let result = tool.execute(&args, &config, fsm).await.unwrap();
+
+    assert!(result.contains("Patch from hash"));
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "struct Agent;\n\nimpl Agent {\n    fn prepare_request(&self) -> u8 {\n        2\n    }\n\n    fn other(&self) {}\n}\n"
+    );
+}
+
+#[tokio::test]
+async fn test_replace_unknown_symbol_fails() {
+    let tmp_dir = Builder::new().prefix("test-fsm-").tempdir().unwrap();
+    let file_path = tmp_dir.path().join("lib.rs");
+    fs::write(&file_path, "fn main() {}\n").unwrap();
+    let config = Config {
+        accessible_paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+    let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+
+    let args = serde_json::json!({
+        "replace_symbols": [{
+            "file_path": file_path.to_str().unwrap(),
+            "symbol": "helper",
+            "new_content": ""
+        }]
+    });
+
+    let result = FileEditorTool::default().execute(&args, &config, fsm).await;
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("Replace symbol request #0"));
+    assert!(error.contains("Symbol 'helper' not found"));
+}
+
+#[tokio::test]
+async fn test_edit_introducing_syntax_error_is_rejected() {
+    let (tmp_dir, _, fsm, mut config) = setup_fsm("");
+    config.validate_edit_syntax = true;
+    let file_path = tmp_dir.path().join("main.rs");
+    let original = "fn main() {\n    let x = 1;\n}\n";
+    fs::write(&file_path, original).unwrap();
+    let file_path_str = file_path.to_str().unwrap().to_string();
+    fsm.lock().unwrap().open_file(&file_path_str).unwrap();
+    let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
+    let anchor = get_anchor(&state, 1);
+
+    let args = serde_json::json!({
+        "replaces": [{
+            "file_path": file_path_str,
+            "anchor_range_begin": anchor,
+            "anchor_range_end": anchor,
+            "new_content": "    let x = ;"
+        }]
+    });
+
+    let result = FileEditorTool::default()
+        .execute(&args, &config, fsm)
+        .await
+        .unwrap_err()
+        .to_string();
+
+    assert!(result.contains("introduces syntax errors (0 before, 1 after)"));
+    assert!(result.contains("Rejected content:"));
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), original);
+}
+
+#[tokio::test]
+async fn test_formatter_changes_keep_unchanged_lids() {
+    let (tmp_dir, _, fsm, mut config) = setup_fsm("");
+    config.formatters = vec!["rs=sed 's/let  *y/let y/'".to_string()];
+    let file_path = tmp_dir.path().join("main.rs");
+    fs::write(&file_path, "fn main() {\n    let x = 1;\n}\n").unwrap();
+    let file_path_str = file_path.to_str().unwrap().to_string();
+    fsm.lock().unwrap().open_file(&file_path_str).unwrap();
+    let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
+    let anchor = get_anchor(&state, 1);
+
+    let args = serde_json::json!({
+        "inserts": [{
+            "file_path": file_path_str,
+            "at_position": "after_anchor",
+            "context_anchor": anchor,
+            "new_content": "    let    y = 2;"
+        }]
+    });
+
+    let result = FileEditorTool::default()
+        .execute(&args, &config, fsm.clone())
+        .await
+        .unwrap();
+
+    assert!(result.contains("applied successfully"), "{result}");
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "fn main() {\n    let x = 1;\n    let y = 2;\n}\n"
+    );
+    let new_state = fsm.lock().unwrap().open_files[&file_path_str].clone();
+    assert_eq!(new_state.lid_at_line(1), state.lid_at_line(1));
+    assert_eq!(new_state.lid_at_line(2), state.lid_at_line(2));
+    assert_eq!(new_state.lid_at_line(4), state.lid_at_line(3));
+}
+
+#[tokio::test]
+async fn test_written_edits_are_sent_to_language_servers() {
+    let (tmp_dir, file_path, fsm, config) = setup_fsm("line 1\nline 2");
+    let file_path_str = file_path.to_str().unwrap().to_string();
+    let (client, changes) = lsp::connect_fake_server(tmp_dir.path());
+    client.sync_document(&file_path, "line 1\nline 2").unwrap();
+    let lsp_manager = Arc::new(Mutex::new(LspManager::new(tmp_dir.path().to_path_buf())));
+    lsp_manager
+        .lock()
+        .unwrap()
+        .attach("fake-ls", client.clone());
+    fsm.lock().unwrap().open_file(&file_path_str).unwrap();
+    let state = fsm.lock().unwrap().open_files[&file_path_str].clone();
+    let anchor = get_anchor(&state, 1);
+
+    let args = serde_json::json!({
+        "replaces": [{
+            "file_path": file_path_str,
+            "anchor_range_begin": anchor,
+            "anchor_range_end": anchor,
+            "new_content": "line two"
+        }]
+    });
+    FileEditorTool::new(lsp_manager)
+        .execute(&args, &config, fsm)
+        .await
+        .unwrap();
+
+    let change = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(change["contentChanges"][0]["text"], "line 1\nline two");
+    client.shutdown();
+}
+
+#[test]
+fn test_formatter_with_large_output() {
+    let config = Config::default();
+    // Larger than a pipe buffer, so `cat` blocks on stdout before it has read all input.
+    let content = "let x = 1;\n".repeat(50_000);
+
+    let formatted = super::run_formatter("cat", &content, &config).unwrap();
+
+    assert_eq!(formatted, content);
+}
+
+#[test]
+fn test_hanging_formatter_is_killed() {
+    let config = Config {
+        timeout_seconds: 1,
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+
+    let error = super::run_formatter("exec sleep 30", "", &config).unwrap_err();
+
+    assert_eq!(error.to_string(), "timed out after 1 seconds");
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
@@ -14,9 +14,11 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::NoneAsEmptyString;
 use serde_with::serde_as;
+use std::ffi::OsString;
 use std::path::Path;
 use std::process::Stdio;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 
@@ -44,7 +46,7 @@ impl Tool for ShellTool {
             name: "execute_shell_command".to_string(),
             description: Some(
                 "Executes a shell command.
-The output and the exit code will be returned. Always do a short analysis of the result."
+Returns a JSON object with the fields `exit_code` (or `signal` if the process was killed), `duration_ms`, `stdout` and `stderr`. Empty streams are omitted. Always do a short analysis of the result."
                     .to_string(),
             ),
             strict: Some(true),
@@ -94,11 +96,14 @@ The output and the exit code will be returned. Always do a short analysis of the
     async fn execute(
         &self,
         args: &Value,
-        _config: &Config,
+        config: &Config,
         _fsm: Arc<Mutex<FileStateManager>>,
     ) -> Result<String> {
         let args: ShellCommandArgs = serde_json::from_value(args.clone())?;
-        execute_shell_command(&args.command, args.workdir.as_deref()).await
+        let env = permissions::command_env(config);
+        execute_shell_command(&args.command, args.workdir.as_deref(), &env)
+            .await?
+            .to_llm_string()
     }
 
     fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
@@ -123,14 +128,82 @@ The output and the exit code will be returned. Always do a short analysis of the
     }
 }
 
-pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Result<String> {
+/// The structured result of a shell command, returned to the LLM.
+///
+/// ### Reasoning
+/// Keeping stdout and stderr apart lets the LLM distinguish diagnostics from data,
+/// e.g. when a command's output is meant to be parsed. Empty fields are omitted from
+/// the serialized form to save tokens.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ShellCommandResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The signal that terminated the process, if it did not exit normally.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    pub duration_ms: u128,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+}
+
+impl ShellCommandResult {
+    /// Serializes the result as compact, single-line JSON for the LLM.
+    pub fn to_llm_string(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn exit_message(&self) -> String {
+        match (self.exit_code, self.signal) {
+            (Some(code), _) => format!("Exit code: {code}"),
+            (None, Some(signal)) => format!("Process terminated by signal {signal}"),
+            (None, None) => "Process terminated by signal".to_string(),
+        }
+    }
+
+    /// The last `OUTPUT_TAIL_LINES` lines of stdout followed by stderr. Used by tools that
+    /// couldn't parse the output, where the end of it is the best hint at what went wrong.
+    pub fn output_tail(&self) -> String {
+        let combined = format!("{}{}", self.stdout, self.stderr);
+        let lines: Vec<&str> = combined.lines().collect();
+        lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n")
+    }
+}
+
+/// How many lines of output `ShellCommandResult::output_tail` returns.
+const OUTPUT_TAIL_LINES: usize = 30;
+
+/// Validates the working directory of a command run by a tool like `check` or `run_tests`.
+pub fn plan_workdir(workdir: Option<&str>, config: &Config) -> Result<()> {
+    if let Some(workdir) = workdir {
+        let path = Path::new(workdir);
+        permissions::is_path_accessible(path, &config.accessible_paths)?;
+        if !path.is_dir() {
+            return Err(anyhow!(
+                "Validation failed: Working directory '{}' is not a directory or does not exist.",
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs `command` through `sh -c` with exactly the environment variables in `env`.
+pub async fn execute_shell_command(
+    command: &str,
+    workdir: Option<&str>,
+    env: &[(OsString, OsString)],
+) -> Result<ShellCommandResult> {
     let mut cmd = Command::new("sh");
     cmd.arg("-c").arg(command);
+    cmd.env_clear().envs(env.iter().cloned());
 
     if let Some(dir) = workdir {
         cmd.current_dir(dir);
     }
 
+    let started_at = Instant::now();
     let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
 
     let stdout = child
@@ -145,9 +218,10 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
     let mut stdout_reader = BufReader::new(stdout).lines();
     let mut stderr_reader = BufReader::new(stderr).lines();
 
-    let mut output = String::new();
+    let mut result = ShellCommandResult::default();
     let mut stdout_done = false;
     let mut stderr_done = false;
+    let stderr_marker = style("stderr|").red().dim();
 
     loop {
         tokio::select! {
@@ -155,9 +229,9 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
             line = stderr_reader.next_line(), if !stderr_done => {
                 match line {
                     Ok(Some(line)) => {
-                        eprintln!("{line}");
-                        output.push_str(&line);
-                        output.push('\n');
+                        eprintln!("{stderr_marker} {line}");
+                        result.stderr.push_str(&line);
+                        result.stderr.push('\n');
                     }
                     Ok(None) => stderr_done = true,
                     Err(e) => return Err(anyhow!("Error reading stderr: {e}")),
@@ -167,8 +241,8 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
                 match line {
                     Ok(Some(line)) => {
                         println!("{line}");
-                        output.push_str(&line);
-                        output.push('\n');
+                        result.stdout.push_str(&line);
+                        result.stdout.push('\n');
                     }
                     Ok(None) => stdout_done = true,
                     Err(e) => return Err(anyhow!("Error reading stdout: {e}")),
@@ -181,17 +255,118 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
     }
 
     let status = child.wait().await?;
-    let exit_message = if let Some(code) = status.code() {
-        format!("Exit code: {code}")
-    } else {
-        "Process terminated by signal".to_string()
-    };
+    result.duration_ms = started_at.elapsed().as_millis();
+    result.exit_code = status.code();
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        result.signal = status.signal();
+    }
 
-    let styled_exit_message = style(&exit_message).bold().to_string();
+    let styled_exit_message = style(result.exit_message()).bold().to_string();
     println!("\n{styled_exit_message}");
 
-    output.push('\n');
-    output.push_str(&exit_message);
+    Ok(result)
+}
+
+/// Runs `command` like `execute_shell_command`, but without echoing its output to the terminal.
+/// Used by tools that post-process the output before showing it.
+pub async fn capture_shell_command(
+    command: &str,
+    workdir: Option<&str>,
+    env: &[(OsString, OsString)],
+) -> Result<ShellCommandResult> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd.env_clear().envs(env.iter().cloned());
+
+    if let Some(dir) = workdir {
+        cmd.current_dir(dir);
+    }
 
-    Ok(output)
+    let started_at = Instant::now();
+    let output = cmd.stdin(Stdio::null()).output().await?;
+    let mut result = ShellCommandResult {
+        exit_code: output.status.code(),
+        signal: None,
+        duration_ms: started_at.elapsed().as_millis(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        result.signal = output.status.signal();
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_streams_are_captured_separately() {
+        let result = execute_shell_command("echo data; echo warning >&2", None, &[])
+            .await
+            .unwrap();
+        assert_eq!(result.stdout, "data\n");
+        assert_eq!(result.stderr, "warning\n");
+        assert_eq!(result.exit_code, Some(0));
+        assert_eq!(result.signal, None);
+    }
+
+    #[tokio::test]
+    async fn test_exit_code_is_reported() {
+        let result = execute_shell_command("exit 3", None, &[]).await.unwrap();
+        assert_eq!(result.exit_code, Some(3));
+        assert!(result.stdout.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_signal_is_reported() {
+        let result = execute_shell_command("kill -9 $$", None, &[])
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, None);
+        assert_eq!(result.signal, Some(9));
+    }
+
+    #[tokio::test]
+    async fn test_only_given_env_vars_are_visible() {
+        let env = vec![("ALORS_TEST_VAR".into(), "visible".into())];
+        let result = execute_shell_command("echo \"$ALORS_TEST_VAR:$HOME\"", None, &env)
+            .await
+            .unwrap();
+        assert_eq!(result.stdout, "visible:\n");
+    }
+
+    #[test]
+    fn test_output_tail() {
+        let result = ShellCommandResult {
+            stdout: (1..=40).map(|i| format!("line {i}\n")).collect(),
+            stderr: "error: failed\n".to_string(),
+            ..Default::default()
+        };
+        let tail = result.output_tail();
+        assert_eq!(tail.lines().count(), OUTPUT_TAIL_LINES);
+        assert!(tail.starts_with("line 12\n"));
+        assert!(tail.ends_with("line 40\nerror: failed"));
+    }
+
+    #[test]
+    fn test_llm_string_omits_empty_fields() {
+        let result = ShellCommandResult {
+            exit_code: Some(0),
+            signal: None,
+            duration_ms: 12,
+            stdout: "hello\n".to_string(),
+            stderr: String::new(),
+        };
+        assert_eq!(
+            result.to_llm_string().unwrap(),
+            r#"{"exit_code":0,"duration_ms":12,"stdout":"hello\n"}"#
+        );
+    }
 }
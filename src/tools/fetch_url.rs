@@ -0,0 +1,409 @@
+//! # Fetch URL Tool
+//!
+//! This module provides the `fetch_url` tool, which fetches a web page with a GET request,
+//! converts HTML to markdown and truncates the result to a token budget. Which domains may
+//! be fetched is controlled by `allowed_domains` and `denied_domains` in the `Config`.
+
+use crate::config::Config;
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::tools::Tool;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use openrouter_api::models::tool::FunctionDescription;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+/// A rough estimate of the number of characters per token, used for truncation.
+const CHARS_PER_TOKEN: usize = 4;
+/// The maximum number of redirects that are followed.
+const MAX_REDIRECTS: usize = 5;
+/// The maximum number of bytes read from a response body.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+static NON_CONTENT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(script|style|noscript|svg|head)\b.*?</(script|style|noscript|svg|head)>")
+        .unwrap()
+});
+static BLANK_LINES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n[ \t]*(\n[ \t]*)+\n").unwrap());
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FetchUrlArgs {
+    pub url: String,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+}
+
+/// Validates the arguments for a fetch.
+/// Ensures that the URL is a valid http(s) URL and its domain is not denied.
+fn plan_fetch(args: &FetchUrlArgs, config: &Config) -> Result<Url> {
+    let url =
+        Url::parse(args.url.trim()).map_err(|e| anyhow!("Invalid URL '{}': {e}", args.url))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!(
+            "Unsupported URL scheme '{}'. Only http and https are supported.",
+            url.scheme()
+        ));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("URL '{url}' has no host."))?;
+    permissions::is_domain_denied(host, &config.denied_domains)?;
+    Ok(url)
+}
+
+pub struct FetchUrlTool;
+
+#[async_trait]
+impl Tool for FetchUrlTool {
+    fn name(&self) -> &'static str {
+        "fetch_url"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "fetch_url".to_string(),
+            description: Some(
+                "Fetches a web page with a GET request and returns its content. HTML is converted to markdown.
+Use it to read documentation of libraries and APIs, e.g. on docs.rs. Long pages are truncated to `max_tokens`."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "The http or https URL to fetch."
+                    },
+                    "max_tokens": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "The maximum number of tokens of content to return. Defaults to the configured budget."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["url", "max_tokens"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: FetchUrlArgs = serde_json::from_value(args.clone())?;
+        let url = plan_fetch(&args, config)?;
+        Ok(format!("GET {url}"))
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: FetchUrlArgs = serde_json::from_value(args.clone())?;
+        let url = plan_fetch(&args, config)?;
+        execute_fetch_url(
+            url,
+            args.max_tokens.unwrap_or(config.fetch_max_tokens),
+            config,
+        )
+        .await
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        let args: FetchUrlArgs = serde_json::from_value(args.clone())?;
+        let Ok(url) = plan_fetch(&args, config) else {
+            return Ok(false);
+        };
+        let host = url.host_str().unwrap_or_default();
+        Ok(permissions::is_domain_allowed(host, &config.allowed_domains).is_ok())
+    }
+}
+
+pub async fn execute_fetch_url(url: Url, max_tokens: usize, config: &Config) -> Result<String> {
+    let denied_domains = config.denied_domains.clone();
+    let allowed_domains = config.allowed_domains.clone();
+    let host = url.host_str().unwrap_or_default().to_string();
+    // Redirects are checked against the deny list too, so a denied domain can't be reached
+    // through an allowed one. Redirects to other hosts that are not allowed are not followed,
+    // since the fetch may have been approved only because its domain is allowed.
+    let redirect_policy = reqwest::redirect::Policy::custom(move |attempt| {
+        let target = attempt.url().host_str().unwrap_or_default().to_string();
+        if let Err(e) = permissions::is_domain_denied(&target, &denied_domains) {
+            return attempt.error(e.to_string());
+        }
+        if attempt.previous().len() >= MAX_REDIRECTS
+            || (target != host
+                && permissions::is_domain_allowed(&target, &allowed_domains).is_err())
+        {
+            return attempt.stop();
+        }
+        attempt.follow()
+    });
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .redirect(redirect_policy)
+        .user_agent(concat!("alors/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to fetch {url}: {e}"))?;
+    let status = response.status();
+    let final_url = response.url().clone();
+    if status.is_redirection()
+        && let Some(location) = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+    {
+        let location = final_url
+            .join(location)
+            .map_or_else(|_| location.to_string(), |location| location.to_string());
+        return Err(anyhow!(
+            "GET {final_url} redirected to {location}, which was not followed because its domain is not allowed. Fetch that URL to follow the redirect."
+        ));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("text/plain")
+        .to_string();
+    let (body, truncated) = read_body(response).await?;
+
+    if !status.is_success() {
+        return Err(anyhow!(
+            "GET {final_url} failed with status {status}.\n{}",
+            truncate_to_tokens(&body, 200)
+        ));
+    }
+
+    let content = if content_type.contains("html") {
+        html_to_markdown(&body)
+    } else if content_type.starts_with("text/")
+        || content_type.contains("json")
+        || content_type.contains("xml")
+    {
+        body
+    } else {
+        return Err(anyhow!(
+            "GET {final_url} returned unsupported content type '{content_type}'."
+        ));
+    };
+
+    let total_tokens = content.len().div_ceil(CHARS_PER_TOKEN);
+    let truncated = if truncated {
+        format!(" | Body cut off after {MAX_BODY_BYTES} bytes")
+    } else {
+        String::new()
+    };
+    Ok(format!(
+        "URL: {final_url} | Status: {} | Content-Type: {content_type} | Tokens: ~{total_tokens}{truncated}\n\n{}",
+        status.as_u16(),
+        truncate_to_tokens(&content, max_tokens)
+    ))
+}
+
+/// Reads at most `MAX_BODY_BYTES` of the response body, so a huge page isn't held in memory
+/// before the token budget applies. Returns the body and whether it was cut off.
+async fn read_body(mut response: reqwest::Response) -> Result<(String, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = MAX_BODY_BYTES - body.len();
+        if chunk.len() > remaining {
+            body.extend_from_slice(&chunk[..remaining]);
+            return Ok((String::from_utf8_lossy(&body).into_owned(), true));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((String::from_utf8_lossy(&body).into_owned(), false))
+}
+
+/// Converts an HTML page into markdown, without scripts, styles and excess blank lines.
+pub fn html_to_markdown(html: &str) -> String {
+    let html = NON_CONTENT_RE.replace_all(html, "");
+    let markdown = html2md::parse_html(&html);
+    BLANK_LINES_RE
+        .replace_all(markdown.trim(), "\n\n")
+        .to_string()
+}
+
+/// Truncates content to an estimated token budget, at a line boundary if possible.
+fn truncate_to_tokens(content: &str, max_tokens: usize) -> String {
+    let max_chars = max_tokens * CHARS_PER_TOKEN;
+    if content.len() <= max_chars {
+        return content.to_string();
+    }
+    let mut end = max_chars;
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = content[..end].rfind('\n').filter(|&i| i > 0).unwrap_or(end);
+    format!(
+        "{}\n\n[... truncated, showing ~{max_tokens} of ~{} tokens. Use a larger `max_tokens` to see more.]",
+        &content[..end],
+        content.len().div_ceil(CHARS_PER_TOKEN)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves one fixed response per connection on a local port.
+    async fn serve(content_type: &'static str, body: String) -> String {
+        serve_response(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ))
+        .await
+    }
+
+    /// Serves a raw HTTP response per connection on a local port.
+    async fn serve_response(response: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{address}/docs")
+    }
+
+    #[tokio::test]
+    async fn test_fetch_html_as_markdown() {
+        let url = serve(
+            "text/html; charset=utf-8",
+            "<html><head><title>T</title><style>body{}</style></head><body><h1>Hello</h1><script>alert(1)</script><p>Some <b>docs</b>.</p></body></html>".to_string(),
+        )
+        .await;
+        let config = Config::default();
+        let args = serde_json::json!({ "url": url, "max_tokens": null });
+
+        let result = FetchUrlTool
+            .execute(
+                &args,
+                &config,
+                Arc::new(Mutex::new(FileStateManager::new())),
+            )
+            .await
+            .unwrap();
+
+        assert!(result.starts_with(&format!(
+            "URL: {url} | Status: 200 | Content-Type: text/html"
+        )));
+        assert!(result.contains("Hello\n=========="));
+        assert!(result.contains("Some **docs**."));
+        assert!(!result.contains("alert"));
+        assert!(!result.contains("body{}"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_truncates_to_token_budget() {
+        let body = (0..100).map(|i| format!("line {i}\n")).collect::<String>();
+        let url = serve("text/plain", body).await;
+        let config = Config::default();
+        let args = serde_json::json!({ "url": url, "max_tokens": 10 });
+
+        let result = FetchUrlTool
+            .execute(
+                &args,
+                &config,
+                Arc::new(Mutex::new(FileStateManager::new())),
+            )
+            .await
+            .unwrap();
+
+        assert!(result.contains("line 4\n\n[... truncated, showing ~10 of ~"));
+        assert!(!result.contains("line 5"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_other_domain_is_not_followed() {
+        let url = serve_response(
+            "HTTP/1.1 302 Found\r\nLocation: http://example.invalid/x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        )
+        .await;
+        let config = Config {
+            allowed_domains: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        };
+
+        let error = execute_fetch_url(Url::parse(&url).unwrap(), 100, &config)
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with(&format!(
+            "GET {url} redirected to http://example.invalid/x, which was not followed"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_large_body_is_cut_off() {
+        let url = serve("text/plain", "x".repeat(MAX_BODY_BYTES + 1000)).await;
+
+        let result = execute_fetch_url(Url::parse(&url).unwrap(), 10, &Config::default())
+            .await
+            .unwrap();
+
+        assert!(result.contains(&format!(
+            "Tokens: ~{} | Body cut off after {MAX_BODY_BYTES} bytes",
+            MAX_BODY_BYTES / CHARS_PER_TOKEN
+        )));
+    }
+
+    #[test]
+    fn test_domain_policy() {
+        let config = Config {
+            allowed_domains: vec!["docs.rs".to_string()],
+            denied_domains: vec!["internal.example.com".to_string()],
+            ..Default::default()
+        };
+        let args = |url: &str| serde_json::json!({ "url": url, "max_tokens": null });
+
+        assert!(
+            FetchUrlTool
+                .is_safe_for_auto_execute(&args("https://docs.rs/serde"), &config)
+                .unwrap()
+        );
+        assert!(
+            !FetchUrlTool
+                .is_safe_for_auto_execute(&args("https://example.com"), &config)
+                .unwrap()
+        );
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let denied = FetchUrlTool.preview(
+            &args("https://api.internal.example.com/x"),
+            &config,
+            fsm.clone(),
+        );
+        assert!(denied.unwrap_err().to_string().contains("denied"));
+        let scheme = FetchUrlTool.preview(&args("file:///etc/passwd"), &config, fsm);
+        assert!(
+            scheme
+                .unwrap_err()
+                .to_string()
+                .contains("Unsupported URL scheme")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! # File Outline Tool
+//!
+//! This module provides the `file_outline` tool, which lists the items of source files
+//! (modules, types, impls, functions) as a nested outline. Each item comes with the LIDs
+//! of its first and last line, so the LLM can read or replace exactly one item.
+
+use crate::config::Config;
+use crate::file_state::FileState;
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::syntax::{self, OutlineItem, SyntaxLanguage};
+use crate::tools::Tool;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use openrouter_api::models::tool::FunctionDescription;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Validates the arguments for an outline.
+/// Ensures that all paths are accessible files in a supported language.
+fn plan_outline(args: &FileOutlineArgs, config: &Config) -> Result<()> {
+    if args.files.is_empty() {
+        return Err(anyhow!("No files were specified."));
+    }
+    for file in &args.files {
+        let path = Path::new(file);
+        permissions::is_path_accessible(path, &config.accessible_paths)?;
+        if !path.is_file() {
+            return Err(anyhow!(
+                "Validation failed: Path '{}' is not a file or does not exist.",
+                path.display()
+            ));
+        }
+        if SyntaxLanguage::from_path(path).is_none() {
+            return Err(anyhow!(
+                "Validation failed: '{}' is not in a supported language (Rust, TypeScript, Python, Go).",
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FileOutlineArgs {
+    pub files: Vec<String>,
+}
+
+pub struct FileOutlineTool;
+
+#[async_trait]
+impl Tool for FileOutlineTool {
+    fn name(&self) -> &'static str {
+        "file_outline"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "file_outline".to_string(),
+            description: Some(
+                r#"Returns a nested outline of the modules, types, impls and functions in source files (Rust, TypeScript, Python, Go).
+Use it to find an item without reading the whole file. Each entry shows its line range and the LIDs of its first and last line, which can be used directly with `edit_files`, or as a range for `read_files`.
+
+Example output:
+```
+File: src/agent.rs | Hash: 931d3b24 | Items: 3
+struct Agent [10-25] lid-8a_x1y2..lid-9c_k3j4
+impl Agent [27-170] lid-9d_a1b2..lid-f0_c3d4
+  fn new [28-40] lid-9e_e5f6..lid-a2_g7h8
+```"#
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "files": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "The files to outline."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["files"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: FileOutlineArgs = serde_json::from_value(args.clone())?;
+        plan_outline(&args, config)?;
+        Ok(args
+            .files
+            .iter()
+            .map(|file| format!("- {file}"))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: FileOutlineArgs = serde_json::from_value(args.clone())?;
+        plan_outline(&args, config)?;
+        let mut manager = fsm.lock().unwrap();
+        execute_file_outline(&args, &mut manager)
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        let args: FileOutlineArgs = serde_json::from_value(args.clone())?;
+        Ok(args.files.iter().all(|file| {
+            permissions::is_path_accessible(Path::new(file), &config.accessible_paths).is_ok()
+        }))
+    }
+}
+
+pub fn execute_file_outline(
+    args: &FileOutlineArgs,
+    file_state_manager: &mut FileStateManager,
+) -> Result<String> {
+    let mut outputs = Vec::new();
+    for file in &args.files {
+        let output = (|| -> Result<String> {
+            let file_state = file_state_manager.open_file(file)?;
+            display_outline(file_state)
+        })()
+        .unwrap_or_else(|e| format!("Error outlining file \"{file}\": {e}"));
+        outputs.push(output);
+    }
+    Ok(outputs.join("\n\n"))
+}
+
+/// Renders the outline of a file, with the LIDs of each item's first and last line.
+pub fn display_outline(file_state: &FileState) -> Result<String> {
+    let language = SyntaxLanguage::from_path(&file_state.path)
+        .ok_or_else(|| anyhow!("Unsupported language"))?;
+    let items = syntax::outline(language, &file_state.get_full_content())?;
+
+    let project_root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let relative_path = file_state
+        .path
+        .strip_prefix(&project_root)
+        .unwrap_or(&file_state.path);
+
+    let mut lines = vec![format!(
+        "File: {} | Hash: {} | Items: {}",
+        relative_path.display(),
+        file_state.get_short_hash(),
+        count_items(&items)
+    )];
+    if items.is_empty() {
+        lines.push("[No items found]".to_string());
+    }
+    render_items(&items, file_state, "", &mut lines);
+    Ok(lines.join("\n"))
+}
+
+fn count_items(items: &[OutlineItem]) -> usize {
+    items
+        .iter()
+        .map(|item| 1 + count_items(&item.children))
+        .sum()
+}
+
+fn render_items(
+    items: &[OutlineItem],
+    file_state: &FileState,
+    indent: &str,
+    lines: &mut Vec<String>,
+) {
+    for item in items {
+        let start_lid = file_state.lid_at_line(item.start_line).unwrap_or_default();
+        let end_lid = file_state.lid_at_line(item.end_line).unwrap_or_default();
+        lines.push(format!(
+            "{indent}{} {} [{}-{}] {start_lid}..{end_lid}",
+            item.kind, item.name, item.start_line, item.end_line
+        ));
+        render_items(&item.children, file_state, &format!("{indent}  "), lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_outline_with_lids() {
+        let tmp_dir = Builder::new().prefix("test-outline").tempdir().unwrap();
+        let file_path = tmp_dir.path().join("lib.rs");
+        fs::write(
+            &file_path,
+            "struct Point;\n\nimpl Point {\n    fn origin() -> Self {\n        Point\n    }\n}\n",
+        )
+        .unwrap();
+        let config = Config {
+            accessible_paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let args = FileOutlineArgs {
+            files: vec![file_path.to_str().unwrap().to_string()],
+        };
+        assert!(plan_outline(&args, &config).is_ok());
+        let mut fsm = FileStateManager::new();
+
+        let output = execute_file_outline(&args, &mut fsm).unwrap();
+
+        let state = fsm.open_file(file_path.to_str().unwrap()).unwrap();
+        let lid = |line| state.lid_at_line(line).unwrap();
+        assert_eq!(
+            output.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                format!("struct Point [1-1] {}..{}", lid(1), lid(1)),
+                format!("impl Point [3-7] {}..{}", lid(3), lid(7)),
+                format!("  fn origin [4-6] {}..{}", lid(4), lid(6)),
+            ]
+        );
+        assert!(output.starts_with("File: "));
+        assert!(
+            output
+                .lines()
+                .next()
+                .unwrap()
+                .ends_with(&format!("| Hash: {} | Items: 3", state.get_short_hash()))
+        );
+    }
+
+    #[test]
+    fn test_unsupported_language_is_rejected() {
+        let tmp_dir = Builder::new().prefix("test-outline").tempdir().unwrap();
+        let file_path = tmp_dir.path().join("notes.txt");
+        fs::write(&file_path, "hello").unwrap();
+        let config = Config {
+            accessible_paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let args = FileOutlineArgs {
+            files: vec![file_path.to_str().unwrap().to_string()],
+        };
+
+        let result = plan_outline(&args, &config);
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("not in a supported language")
+        );
+    }
+}
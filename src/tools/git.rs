@@ -0,0 +1,661 @@
+//! # Git Tool
+//!
+//! This module provides the `git` tool, which shows the status, diffs, history and blame of
+//! the repository. Unlike raw `git` output, lines that exist in the working tree are annotated
+//! with their current LIDs, so the LLM can go straight from "what changed" to an edit.
+
+use crate::config::Config;
+use crate::file_state::FileState;
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::tools::Tool;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use openrouter_api::models::tool::FunctionDescription;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{NoneAsEmptyString, serde_as};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::process::Command;
+
+/// The number of commits shown by the `log` action if no limit is given.
+const DEFAULT_LOG_LIMIT: usize = 20;
+/// The maximum number of diff lines returned, to protect the context window.
+const MAX_DIFF_LINES: usize = 2000;
+
+static HUNK_HEADER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@(.*)$").unwrap());
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitAction {
+    Status,
+    Diff,
+    Log,
+    Blame,
+}
+
+#[serde_as]
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GitArgs {
+    pub action: GitAction,
+    /// A file or directory to restrict the action to. Required for `blame`.
+    #[serde(default)]
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub path: Option<String>,
+    /// The ref to diff against, or to start the log from.
+    #[serde(default)]
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub git_ref: Option<String>,
+    #[serde(default)]
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub start_lid: Option<String>,
+    #[serde(default)]
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub end_lid: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Validates the arguments for a git action.
+fn plan_git(args: &GitArgs, config: &Config) -> Result<()> {
+    if let Some(path) = &args.path {
+        permissions::is_path_accessible(Path::new(path), &config.accessible_paths)?;
+    }
+    if let Some(git_ref) = &args.git_ref
+        && git_ref.starts_with('-')
+    {
+        return Err(anyhow!("Invalid ref '{git_ref}'."));
+    }
+    if args.action == GitAction::Blame {
+        let path = args
+            .path
+            .as_deref()
+            .ok_or_else(|| anyhow!("`path` is required for the `blame` action."))?;
+        if !Path::new(path).is_file() {
+            return Err(anyhow!(
+                "`blame` requires a file, but '{path}' is not a file."
+            ));
+        }
+        if args.start_lid.is_none() || args.end_lid.is_none() {
+            return Err(anyhow!(
+                "`start_lid` and `end_lid` are required for the `blame` action."
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub struct GitTool;
+
+#[async_trait]
+impl Tool for GitTool {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "git".to_string(),
+            description: Some(
+                "Inspects the git repository. Lines that exist in the working tree are annotated with their current LIDs, which can be used directly with `edit_files`.
+
+**Actions**:
+- `status`: Shows the branch and the changed, staged and untracked files, optionally restricted to `path`.
+- `diff`: Shows the changes of the working tree against `git_ref` (default: `HEAD`), optionally restricted to `path`.
+- `log`: Shows the latest commits, optionally starting at `git_ref` and restricted to `path`.
+- `blame`: Shows which commit last changed each line from `start_lid` to `end_lid` in the file `path`."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "enum": ["status", "diff", "log", "blame"],
+                        "description": "The action to perform."
+                    },
+                    "path": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "A file or directory to restrict the action to. Required for `blame`."
+                    },
+                    "git_ref": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "For `diff`: the ref to compare the working tree against. For `log`: the ref to start from."
+                    },
+                    "start_lid": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "For `blame`: the LID of the first line."
+                    },
+                    "end_lid": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "For `blame`: the LID of the last line."
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "For `log`: the maximum number of commits. Defaults to 20."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["action", "path", "git_ref", "start_lid", "end_lid", "limit"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: GitArgs = serde_json::from_value(args.clone())?;
+        plan_git(&args, config)?;
+        let target = args
+            .path
+            .as_deref()
+            .map(|path| format!(" of {path}"))
+            .unwrap_or_default();
+        Ok(match args.action {
+            GitAction::Status => format!("git status{target}"),
+            GitAction::Diff => format!(
+                "git diff{target} against {}",
+                args.git_ref.as_deref().unwrap_or("HEAD")
+            ),
+            GitAction::Log => format!("git log{target}"),
+            GitAction::Blame => format!(
+                "git blame{target}, lines {}..{}",
+                args.start_lid.as_deref().unwrap_or_default(),
+                args.end_lid.as_deref().unwrap_or_default()
+            ),
+        })
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: GitArgs = serde_json::from_value(args.clone())?;
+        plan_git(&args, config)?;
+        execute_git(&args, config, fsm).await
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        let args: GitArgs = serde_json::from_value(args.clone())?;
+        // All actions are read-only.
+        Ok(plan_git(&args, config).is_ok())
+    }
+}
+
+pub async fn execute_git(
+    args: &GitArgs,
+    config: &Config,
+    fsm: Arc<Mutex<FileStateManager>>,
+) -> Result<String> {
+    let path = args
+        .path
+        .as_deref()
+        .map(|path| std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path)));
+    let dir = match &path {
+        Some(path) if path.is_dir() => path.clone(),
+        Some(path) => path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(".")),
+        None => std::env::current_dir()?,
+    };
+    let root = PathBuf::from(
+        run_git(&dir, &["rev-parse", "--show-toplevel"], config)
+            .await?
+            .trim(),
+    );
+    let pathspec: Vec<String> = path
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    match args.action {
+        GitAction::Status => {
+            let mut git_args = vec!["status", "--porcelain=v1", "--branch", "--"];
+            git_args.extend(pathspec.iter().map(String::as_str));
+            let output = run_git(&root, &git_args, config).await?;
+            Ok(render_status(&output))
+        }
+        GitAction::Diff => {
+            let git_ref = args.git_ref.as_deref().unwrap_or("HEAD");
+            let mut git_args = vec!["diff", "--no-color", "--no-ext-diff", git_ref, "--"];
+            git_args.extend(pathspec.iter().map(String::as_str));
+            let output = run_git(&root, &git_args, config).await?;
+            if output.trim().is_empty() {
+                return Ok(format!("No changes against {git_ref}."));
+            }
+            let mut manager = fsm.lock().unwrap();
+            Ok(render_diff(&output, &root, config, &mut manager))
+        }
+        GitAction::Log => {
+            let limit = args.limit.unwrap_or(DEFAULT_LOG_LIMIT).to_string();
+            let mut git_args = vec![
+                "log",
+                "--no-color",
+                "-n",
+                &limit,
+                "--date=short",
+                "--format=%h %ad %an: %s",
+            ];
+            if let Some(git_ref) = &args.git_ref {
+                git_args.push(git_ref);
+            }
+            git_args.push("--");
+            git_args.extend(pathspec.iter().map(String::as_str));
+            let output = run_git(&root, &git_args, config).await?;
+            if output.trim().is_empty() {
+                return Ok("No commits found.".to_string());
+            }
+            Ok(output.trim_end().to_string())
+        }
+        GitAction::Blame => {
+            let path = path.expect("validated by plan_git");
+            let path_str = path.to_string_lossy().to_string();
+            let (start, end, file_state) = {
+                let mut manager = fsm.lock().unwrap();
+                let file_state = manager.open_file(&path_str)?;
+                let start =
+                    file_state.line_of_lid(args.start_lid.as_deref().unwrap_or_default())?;
+                let end = file_state.line_of_lid(args.end_lid.as_deref().unwrap_or_default())?;
+                if start > end {
+                    return Err(anyhow!("`start_lid` must not be after `end_lid`."));
+                }
+                (start, end, file_state.clone())
+            };
+            let range = format!("{start},{end}");
+            let output = run_git(
+                &root,
+                &["blame", "--porcelain", "-L", &range, "--", &path_str],
+                config,
+            )
+            .await?;
+            Ok(render_blame(&output, &file_state))
+        }
+    }
+}
+
+/// Runs a git command and returns its stdout, or its stderr as the error.
+async fn run_git(dir: &Path, args: &[&str], config: &Config) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env_clear()
+        .envs(permissions::command_env(config))
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Renders `git status --porcelain=v1 --branch` as readable groups.
+fn render_status(output: &str) -> String {
+    let mut branch = None;
+    let mut staged = Vec::new();
+    let mut unstaged = Vec::new();
+    let mut untracked = Vec::new();
+    for line in output.lines() {
+        if let Some(info) = line.strip_prefix("## ") {
+            branch = Some(info.to_string());
+            continue;
+        }
+        if line.len() < 4 {
+            continue;
+        }
+        let (index, worktree, file) = (&line[0..1], &line[1..2], &line[3..]);
+        if index == "?" {
+            untracked.push(format!("  {file}"));
+            continue;
+        }
+        if index != " " {
+            staged.push(format!("  {} {file}", describe_status(index)));
+        }
+        if worktree != " " {
+            unstaged.push(format!("  {} {file}", describe_status(worktree)));
+        }
+    }
+
+    let mut sections = vec![format!(
+        "Branch: {}",
+        branch.unwrap_or_else(|| "unknown".to_string())
+    )];
+    for (title, entries) in [
+        ("Staged changes:", staged),
+        ("Unstaged changes:", unstaged),
+        ("Untracked files:", untracked),
+    ] {
+        if !entries.is_empty() {
+            sections.push(format!("{title}\n{}", entries.join("\n")));
+        }
+    }
+    if sections.len() == 1 {
+        sections.push("Working tree clean.".to_string());
+    }
+    sections.join("\n")
+}
+
+fn describe_status(code: &str) -> &'static str {
+    match code {
+        "M" => "modified:",
+        "A" => "added:   ",
+        "D" => "deleted: ",
+        "R" => "renamed: ",
+        "C" => "copied:  ",
+        "U" => "conflict:",
+        "T" => "typechange:",
+        _ => "changed: ",
+    }
+}
+
+/// Renders a unified diff, annotating lines of the working tree with their current LIDs.
+///
+/// Added and context lines show the line number and LID of the working tree, removed lines
+/// show their line number in the old version.
+fn render_diff(output: &str, root: &Path, config: &Config, fsm: &mut FileStateManager) -> String {
+    let mut rendered = Vec::new();
+    let mut file_state: Option<FileState> = None;
+    let mut old_line = 0;
+    let mut new_line = 0;
+    let mut truncated = 0;
+    // Between `diff --git` and the first hunk. Only there, `--- ` and `+++ ` are file headers,
+    // inside a hunk they are removed or added lines.
+    let mut in_header = false;
+
+    for line in output.lines() {
+        if rendered.len() >= MAX_DIFF_LINES {
+            truncated += 1;
+            continue;
+        }
+        if line.starts_with("diff --git ") {
+            file_state = None;
+            in_header = true;
+            if !rendered.is_empty() {
+                rendered.push(String::new());
+            }
+            continue;
+        }
+        if in_header && let Some(new_path) = line.strip_prefix("+++ ") {
+            let Some(relative) = new_path.strip_prefix("b/") else {
+                rendered.push("File: /dev/null (deleted)".to_string());
+                continue;
+            };
+            let path = root.join(relative);
+            file_state = if permissions::is_path_accessible(&path, &config.accessible_paths).is_ok()
+            {
+                fsm.open_file(&path.to_string_lossy()).ok().cloned()
+            } else {
+                None
+            };
+            rendered.push(match &file_state {
+                Some(state) => format!("File: {relative} | Hash: {}", state.get_short_hash()),
+                None => format!("File: {relative} [File not accessible, no LIDs available]"),
+            });
+            continue;
+        }
+        if in_header
+            && (line.starts_with("--- ")
+                || line.starts_with("index ")
+                || line.starts_with("new file mode")
+                || line.starts_with("deleted file mode"))
+        {
+            continue;
+        }
+        if let Some(caps) = HUNK_HEADER_RE.captures(line) {
+            in_header = false;
+            old_line = caps[1].parse().unwrap_or(0);
+            new_line = caps[3].parse().unwrap_or(0);
+            rendered.push(line.to_string());
+            continue;
+        }
+
+        let lid_of = |line_number: usize| {
+            file_state
+                .as_ref()
+                .and_then(|state| state.lid_at_line(line_number))
+                .map(|lid| format!("{lid}: "))
+                .unwrap_or_default()
+        };
+        if let Some(content) = line.strip_prefix('+') {
+            rendered.push(format!("+{new_line:<5}{}{content}", lid_of(new_line)));
+            new_line += 1;
+        } else if let Some(content) = line.strip_prefix('-') {
+            rendered.push(format!("-{old_line:<5}{content}"));
+            old_line += 1;
+        } else if let Some(content) = line.strip_prefix(' ') {
+            rendered.push(format!(" {new_line:<5}{}{content}", lid_of(new_line)));
+            old_line += 1;
+            new_line += 1;
+        } else {
+            // Things like "Binary files differ" or "\ No newline at end of file".
+            rendered.push(line.to_string());
+        }
+    }
+
+    if truncated > 0 {
+        rendered.push(format!(
+            "[... truncated {truncated} diff lines, use `path` to narrow the diff]"
+        ));
+    }
+    rendered.join("\n")
+}
+
+/// Renders `git blame --porcelain` output, with the current LID of every line.
+fn render_blame(output: &str, file_state: &FileState) -> String {
+    // Commit details are only printed for the first line of each commit.
+    let mut commits: HashMap<String, (String, String, String)> = HashMap::new();
+    let mut current: Option<(String, usize)> = None;
+    let mut rendered = Vec::new();
+
+    for line in output.lines() {
+        if let Some(content) = line.strip_prefix('\t') {
+            let Some((sha, line_number)) = current.take() else {
+                continue;
+            };
+            let (author, date, summary) = commits.get(&sha).cloned().unwrap_or_default();
+            let commit = if sha.chars().all(|c| c == '0') {
+                "uncommitted".to_string()
+            } else {
+                sha[..8.min(sha.len())].to_string()
+            };
+            let lid = file_state.lid_at_line(line_number).unwrap_or_default();
+            rendered.push(format!(
+                "{line_number:<5}{lid}: {commit} {date} {author} ({summary}) | {content}"
+            ));
+            continue;
+        }
+        let mut parts = line.split(' ');
+        let first = parts.next().unwrap_or_default();
+        if first.len() == 40 && first.chars().all(|c| c.is_ascii_hexdigit()) {
+            let final_line = parts.nth(1).and_then(|n| n.parse().ok()).unwrap_or(0);
+            commits.entry(first.to_string()).or_default();
+            current = Some((first.to_string(), final_line));
+            continue;
+        }
+        let Some((sha, _)) = &current else {
+            continue;
+        };
+        let entry = commits.entry(sha.clone()).or_default();
+        if let Some(author) = line.strip_prefix("author ") {
+            entry.0 = author.to_string();
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            entry.1 = format_date(time.parse().unwrap_or(0));
+        } else if let Some(summary) = line.strip_prefix("summary ") {
+            entry.2 = summary.to_string();
+        }
+    }
+    rendered.join("\n")
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD` (UTC).
+fn format_date(timestamp: i64) -> String {
+    // Civil-from-days algorithm by Howard Hinnant.
+    let days = timestamp.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_DATE", "2024-03-01T12:00:00Z")
+            .env("GIT_COMMITTER_DATE", "2024-03-01T12:00:00Z")
+            .output()
+            .unwrap();
+        assert!(status.status.success(), "{status:?}");
+    }
+
+    fn setup_repo() -> (tempfile::TempDir, PathBuf, Config) {
+        let tmp_dir = Builder::new().prefix("test-git").tempdir().unwrap();
+        let dir = tmp_dir.path();
+        git(dir, &["init", "-q", "-b", "main"]);
+        git(dir, &["config", "user.name", "Tester"]);
+        git(dir, &["config", "user.email", "tester@example.com"]);
+        let file = dir.join("lib.rs");
+        fs::write(&file, "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+        git(dir, &["add", "."]);
+        git(dir, &["commit", "-q", "-m", "Initial commit"]);
+        let config = Config {
+            accessible_paths: vec![dir.to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        (tmp_dir, file, config)
+    }
+
+    fn args(action: GitAction, path: &Path) -> GitArgs {
+        GitArgs {
+            action,
+            path: Some(path.to_str().unwrap().to_string()),
+            git_ref: None,
+            start_lid: None,
+            end_lid: None,
+            limit: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_diff_status_and_log() {
+        let (tmp_dir, file, config) = setup_repo();
+        fs::write(&file, "fn a() {}\nfn b2() {}\nfn c() {}\n").unwrap();
+        fs::write(tmp_dir.path().join("new.txt"), "new").unwrap();
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+
+        let diff = execute_git(&args(GitAction::Diff, &file), &config, fsm.clone())
+            .await
+            .unwrap();
+        let lid = |line| {
+            fsm.lock()
+                .unwrap()
+                .open_file(file.to_str().unwrap())
+                .unwrap()
+                .lid_at_line(line)
+                .unwrap()
+        };
+        assert!(diff.starts_with("File: lib.rs | Hash: "));
+        assert!(diff.contains("@@ -1,3 +1,3 @@"));
+        assert!(diff.contains(&format!(" 1    {}: fn a() {{}}", lid(1))));
+        assert!(diff.contains("-2    fn b() {}"));
+        assert!(diff.contains(&format!("+2    {}: fn b2() {{}}", lid(2))));
+
+        let status = execute_git(
+            &args(GitAction::Status, tmp_dir.path()),
+            &config,
+            fsm.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(status.starts_with("Branch: main"));
+        assert!(status.contains("Unstaged changes:\n  modified: lib.rs"));
+        assert!(status.contains("Untracked files:\n  new.txt"));
+
+        let log = execute_git(&args(GitAction::Log, &file), &config, fsm)
+            .await
+            .unwrap();
+        assert!(log.ends_with(" 2024-03-01 Tester: Initial commit"));
+    }
+
+    #[tokio::test]
+    async fn test_blame_lid_range() {
+        let (_tmp_dir, file, config) = setup_repo();
+        fs::write(&file, "fn a() {}\nfn b2() {}\nfn c() {}\n").unwrap();
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let (first, second) = {
+            let mut manager = fsm.lock().unwrap();
+            let state = manager.open_file(file.to_str().unwrap()).unwrap();
+            (state.lid_at_line(1).unwrap(), state.lid_at_line(2).unwrap())
+        };
+        let mut blame_args = args(GitAction::Blame, &file);
+        blame_args.start_lid = Some(first.clone());
+        blame_args.end_lid = Some(second.clone());
+
+        let blame = execute_git(&blame_args, &config, fsm).await.unwrap();
+
+        let lines: Vec<&str> = blame.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("1    {first}: ")));
+        assert!(lines[0].ends_with(" 2024-03-01 Tester (Initial commit) | fn a() {}"));
+        assert!(lines[1].starts_with(&format!("2    {second}: uncommitted ")));
+        assert!(lines[1].ends_with("| fn b2() {}"));
+    }
+
+    #[test]
+    fn test_added_lines_like_file_headers_stay_in_the_hunk() {
+        let output = "diff --git a/a.txt b/a.txt\n--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n keep\n-- old\n+++ new\n";
+
+        let rendered = render_diff(
+            output,
+            Path::new("/nonexistent"),
+            &Config::default(),
+            &mut FileStateManager::new(),
+        );
+
+        assert_eq!(
+            rendered,
+            "File: a.txt [File not accessible, no LIDs available]\n@@ -1,2 +1,2 @@\n 1    keep\n-2    - old\n+2    ++ new"
+        );
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_709_294_400), "2024-03-01");
+    }
+}
@@ -0,0 +1,481 @@
+//! # Language Server Tool
+//!
+//! This module provides the `language_server` tool, which asks the configured language
+//! server for diagnostics, definitions, references and hover information. Positions are
+//! given as LIDs and results are rendered as LIF snippets, so they can be used directly
+//! for edits.
+
+use crate::config::Config;
+use crate::diagnostics::{self, Diagnostic, Severity};
+use crate::file_state::RangeSpec;
+use crate::file_state_manager::FileStateManager;
+use crate::lsp::{self, Location, LspDiagnostic, LspManager};
+use crate::permissions;
+use crate::tools::Tool;
+use crate::tools::read_files::merge_ranges;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use openrouter_api::models::tool::FunctionDescription;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use serde_with::{NoneAsEmptyString, serde_as};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The number of lines shown before and after each location.
+const CONTEXT_LINES: usize = 1;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageServerAction {
+    Diagnostics,
+    Definition,
+    References,
+    Hover,
+}
+
+#[serde_as]
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LanguageServerArgs {
+    pub action: LanguageServerAction,
+    pub file_path: String,
+    /// The LID of the line containing the symbol. Required for all actions except `diagnostics`.
+    #[serde(default)]
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub lid: Option<String>,
+    /// The symbol on that line. Defaults to the first non-whitespace character.
+    #[serde(default)]
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub symbol: Option<String>,
+}
+
+/// A validated request, with the position resolved from the LID.
+struct PlannedRequest {
+    path: PathBuf,
+    content: String,
+    /// The 0-indexed line and UTF-16 column of the symbol.
+    position: Option<(u32, u32)>,
+}
+
+/// Validates the arguments and resolves the LID and symbol to a position.
+fn plan_request(
+    args: &LanguageServerArgs,
+    config: &Config,
+    fsm: &mut FileStateManager,
+) -> Result<PlannedRequest> {
+    permissions::is_path_accessible(Path::new(&args.file_path), &config.accessible_paths)?;
+    let file_state = fsm.open_file(&args.file_path)?;
+
+    let position = match (args.action, &args.lid) {
+        (LanguageServerAction::Diagnostics, _) => None,
+        (action, None) => {
+            return Err(anyhow!("`lid` is required for the `{action:?}` action."));
+        }
+        (_, Some(lid)) => {
+            let line_number = file_state.line_of_lid(lid)?;
+            let line = file_state
+                .lines
+                .values()
+                .nth(line_number - 1)
+                .map(|(content, _)| content.as_str())
+                .unwrap_or_default();
+            let byte_offset = match &args.symbol {
+                Some(symbol) => line.find(symbol.as_str()).ok_or_else(|| {
+                    anyhow!("Symbol `{symbol}` not found on line {line_number} ({lid}).")
+                })?,
+                None => line.len() - line.trim_start().len(),
+            };
+            Some((line_number as u32 - 1, lsp::utf16_column(line, byte_offset)))
+        }
+    };
+
+    Ok(PlannedRequest {
+        path: file_state.path.clone(),
+        content: file_state.get_full_content(),
+        position,
+    })
+}
+
+/// The raw answer of the language server.
+enum Answer {
+    Diagnostics(Option<Vec<LspDiagnostic>>),
+    Locations(Vec<Location>),
+    Hover(Option<String>),
+}
+
+/// Sends the request to the language server. This blocks until the server answers.
+fn run_request(
+    action: LanguageServerAction,
+    request: &PlannedRequest,
+    lsp_manager: &Mutex<LspManager>,
+    config: &Config,
+) -> Result<Answer> {
+    let client = lsp_manager
+        .lock()
+        .unwrap()
+        .client_for(&request.path, config)?;
+    client.sync_document(&request.path, &request.content)?;
+
+    let text_document = json!({ "uri": lsp::path_to_uri(&request.path) });
+    let position = request
+        .position
+        .map(|(line, character)| json!({ "line": line, "character": character }));
+    match action {
+        LanguageServerAction::Diagnostics => Ok(Answer::Diagnostics(
+            client.wait_for_diagnostics(&request.path, lsp::DIAGNOSTICS_TIMEOUT),
+        )),
+        LanguageServerAction::Definition => {
+            let result = client.request(
+                "textDocument/definition",
+                json!({ "textDocument": text_document, "position": position }),
+            )?;
+            Ok(Answer::Locations(lsp::parse_locations(result)))
+        }
+        LanguageServerAction::References => {
+            let result = client.request(
+                "textDocument/references",
+                json!({
+                    "textDocument": text_document,
+                    "position": position,
+                    "context": { "includeDeclaration": true }
+                }),
+            )?;
+            Ok(Answer::Locations(lsp::parse_locations(result)))
+        }
+        LanguageServerAction::Hover => {
+            let result = client.request(
+                "textDocument/hover",
+                json!({ "textDocument": text_document, "position": position }),
+            )?;
+            Ok(Answer::Hover(lsp::hover_text(&result)))
+        }
+    }
+}
+
+/// The tool shares its `LspManager` with the `Agent`, which stops all servers when the
+/// session ends.
+pub struct LanguageServerTool {
+    lsp_manager: Arc<Mutex<LspManager>>,
+}
+
+impl LanguageServerTool {
+    pub fn new(lsp_manager: Arc<Mutex<LspManager>>) -> Self {
+        Self { lsp_manager }
+    }
+}
+
+#[async_trait]
+impl Tool for LanguageServerTool {
+    fn name(&self) -> &'static str {
+        "language_server"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "language_server".to_string(),
+            description: Some(
+                "Asks the language server (e.g. rust-analyzer) about the code. Results are shown as LIF snippets.
+
+**Actions**:
+- `diagnostics`: Lists the errors and warnings of `file_path`. Faster than a full build for checking a single file.
+- `definition`: Finds where `symbol` on the line `lid` is defined.
+- `references`: Finds all usages of `symbol` on the line `lid`, including its declaration.
+- `hover`: Shows the type and documentation of `symbol` on the line `lid`."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "enum": ["diagnostics", "definition", "references", "hover"],
+                        "description": "The action to perform."
+                    },
+                    "file_path": {
+                        "type": "string",
+                        "description": "The file to ask about."
+                    },
+                    "lid": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The LID of the line containing the symbol. Required for all actions except `diagnostics`."
+                    },
+                    "symbol": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The identifier on that line, e.g. `prepare_request`. Its first occurrence on the line is used."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["action", "file_path", "lid", "symbol"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: LanguageServerArgs = serde_json::from_value(args.clone())?;
+        plan_request(&args, config, &mut fsm.lock().unwrap())?;
+        let target = match (&args.symbol, &args.lid) {
+            (Some(symbol), Some(lid)) => format!("`{symbol}` at {} {lid}", args.file_path),
+            (None, Some(lid)) => format!("{} {lid}", args.file_path),
+            _ => args.file_path.clone(),
+        };
+        let action = match args.action {
+            LanguageServerAction::Diagnostics => "diagnostics",
+            LanguageServerAction::Definition => "the definition",
+            LanguageServerAction::References => "references",
+            LanguageServerAction::Hover => "hover information",
+        };
+        Ok(format!("Ask the language server for {action} of {target}"))
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: LanguageServerArgs = serde_json::from_value(args.clone())?;
+        let request = plan_request(&args, config, &mut fsm.lock().unwrap())?;
+
+        let lsp_manager = self.lsp_manager.clone();
+        let request_config = config.clone();
+        let action = args.action;
+        let (request, answer) = tokio::task::spawn_blocking(move || {
+            let answer = run_request(action, &request, &lsp_manager, &request_config);
+            (request, answer)
+        })
+        .await?;
+
+        let mut manager = fsm.lock().unwrap();
+        Ok(match answer? {
+            Answer::Diagnostics(diagnostics) => {
+                render_lsp_diagnostics(&request.path, diagnostics, config, &mut manager)
+            }
+            Answer::Locations(locations) => {
+                render_locations(action, &locations, config, &mut manager)
+            }
+            Answer::Hover(text) => match text {
+                Some(text) => text,
+                None => "No hover information available.".to_string(),
+            },
+        })
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        let args: LanguageServerArgs = serde_json::from_value(args.clone())?;
+        Ok(
+            permissions::is_path_accessible(Path::new(&args.file_path), &config.accessible_paths)
+                .is_ok(),
+        )
+    }
+}
+
+/// Returns the path relative to the current directory, for display.
+fn display_path(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    path.strip_prefix(&cwd)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn render_lsp_diagnostics(
+    path: &Path,
+    diagnostics: Option<Vec<LspDiagnostic>>,
+    config: &Config,
+    fsm: &mut FileStateManager,
+) -> String {
+    let file = display_path(path);
+    let Some(diagnostics) = diagnostics else {
+        return format!("The language server has not reported diagnostics for {file} yet.");
+    };
+    if diagnostics.is_empty() {
+        return format!("No diagnostics reported for {file}.");
+    }
+    let diagnostics: Vec<Diagnostic> = diagnostics
+        .into_iter()
+        .map(|diagnostic| Diagnostic {
+            file: file.clone(),
+            line: diagnostic.range.start.line as usize + 1,
+            end_line: diagnostic.range.end.line as usize + 1,
+            column: diagnostic.range.start.character as usize + 1,
+            severity: match diagnostic.severity {
+                Some(1) => Severity::Error,
+                Some(2) => Severity::Warning,
+                _ => Severity::Note,
+            },
+            code: diagnostic.code.map(|code| match code {
+                Value::String(code) => code,
+                code => code.to_string(),
+            }),
+            message: match diagnostic.source {
+                Some(source) => format!("{} ({source})", diagnostic.message),
+                None => diagnostic.message,
+            },
+        })
+        .collect();
+    let base_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    diagnostics::render_diagnostics(&diagnostics, &base_dir, config, fsm)
+}
+
+/// Renders locations grouped by file, as LIF snippets with some context.
+fn render_locations(
+    action: LanguageServerAction,
+    locations: &[Location],
+    config: &Config,
+    fsm: &mut FileStateManager,
+) -> String {
+    let noun = match action {
+        LanguageServerAction::Definition => "definitions",
+        _ => "references",
+    };
+    if locations.is_empty() {
+        return format!("# No {noun} found.");
+    }
+
+    let mut files: Vec<(PathBuf, Vec<&Location>)> = Vec::new();
+    for location in locations {
+        let Some(path) = lsp::uri_to_path(&location.uri) else {
+            continue;
+        };
+        match files.iter_mut().find(|(file, _)| *file == path) {
+            Some((_, group)) => group.push(location),
+            None => files.push((path, vec![location])),
+        }
+    }
+
+    let mut sections = Vec::new();
+    for (path, group) in &files {
+        let mut lines: Vec<usize> = group
+            .iter()
+            .map(|location| location.range.start.line as usize + 1)
+            .collect();
+        lines.dedup();
+        let line_list = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let path_str = path.to_string_lossy();
+        let file_state = if permissions::is_path_accessible(path, &config.accessible_paths).is_ok()
+        {
+            fsm.open_file(&path_str).ok()
+        } else {
+            None
+        };
+        match file_state {
+            Some(state) => {
+                let ranges = merge_ranges(
+                    lines
+                        .iter()
+                        .map(|line| RangeSpec {
+                            start_line: line.saturating_sub(CONTEXT_LINES).max(1),
+                            end_line: (line + CONTEXT_LINES).min(state.lines.len()),
+                        })
+                        .collect(),
+                );
+                sections.push(format!(
+                    "Locations on lines {line_list}:\n{}",
+                    state.display_lif_contents_for_ranges(Some(&ranges))
+                ));
+            }
+            None => sections.push(format!(
+                "{}: lines {line_list} [File not accessible, no LIDs available]",
+                display_path(path)
+            )),
+        }
+    }
+
+    format!(
+        "Found {} {noun} in {} files.\n\n{}",
+        locations.len(),
+        files.len(),
+        sections.join("\n\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    #[tokio::test]
+    async fn test_language_server_with_fake_server() {
+        let tmp_dir = Builder::new().prefix("test-lsp-tool").tempdir().unwrap();
+        let file_path = tmp_dir.path().join("main.rs");
+        fs::write(&file_path, "fn main() {\n    helper();\n}\n").unwrap();
+        let file_path_str = file_path.to_str().unwrap().to_string();
+        let config = Config {
+            accessible_paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+            language_servers: vec!["rs=fake-ls".to_string()],
+            ..Default::default()
+        };
+        let (client, _changes) = lsp::connect_fake_server(tmp_dir.path());
+        let lsp_manager = Arc::new(Mutex::new(LspManager::new(tmp_dir.path().to_path_buf())));
+        lsp_manager.lock().unwrap().attach("fake-ls", client);
+        let tool = LanguageServerTool::new(lsp_manager.clone());
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let (first_lid, second_lid) = {
+            let mut manager = fsm.lock().unwrap();
+            let state = manager.open_file(&file_path_str).unwrap();
+            (state.lid_at_line(1).unwrap(), state.lid_at_line(2).unwrap())
+        };
+
+        let diagnostics = tool
+            .execute(
+                &json!({ "action": "diagnostics", "file_path": file_path_str, "lid": null, "symbol": null }),
+                &config,
+                fsm.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(diagnostics.contains(&format!("  error[E0001] 1:4 {first_lid}: fake error")));
+
+        let references = tool
+            .execute(
+                &json!({ "action": "references", "file_path": file_path_str, "lid": second_lid, "symbol": "helper" }),
+                &config,
+                fsm.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(references.starts_with("Found 2 references in 1 files."));
+        assert!(references.contains("Locations on lines 1, 2:"));
+        assert!(references.contains(&format!("2    {second_lid}:     helper();")));
+
+        let hover = tool
+            .execute(
+                &json!({ "action": "hover", "file_path": file_path_str, "lid": second_lid, "symbol": "helper" }),
+                &config,
+                fsm.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(hover, "fn fake()");
+
+        let missing_symbol = tool
+            .execute(
+                &json!({ "action": "definition", "file_path": file_path_str, "lid": second_lid, "symbol": "nope" }),
+                &config,
+                fsm,
+            )
+            .await;
+        assert!(
+            missing_symbol
+                .unwrap_err()
+                .to_string()
+                .contains("Symbol `nope` not found on line 2")
+        );
+
+        lsp_manager.lock().unwrap().stop_all();
+    }
+}
@@ -1,14 +1,24 @@
-use crate::{config::Config, path_expander, permissions, tools::Tool};
+use crate::{
+    config::Config,
+    path_expander::{self, GlobFilter},
+    permissions,
+    tools::Tool,
+};
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use openrouter_api::models::tool::FunctionDescription;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use crate::file_state_manager::FileStateManager;
 
+/// The maximum number of lines in a listing before it is truncated.
+const MAX_LIST_ENTRIES: usize = 500;
+
 /// The main validation and planning logic for the `list_files` tool.
 /// Ensures the path is an accessible, existing directory.
 fn plan_list_files(args: &ListFilesArgs, config: &Config) -> Result<()> {
@@ -25,9 +35,19 @@ fn plan_list_files(args: &ListFilesArgs, config: &Config) -> Result<()> {
     Ok(())
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Default)]
 pub struct ListFilesArgs {
     pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_size: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_line_count: Option<bool>,
 }
 
 pub struct ListFilesTool;
@@ -42,7 +62,9 @@ impl Tool for ListFilesTool {
         FunctionDescription {
             name: "list_files".to_string(),
             description: Some(
-                "Lists all files in a given directory recursively, respecting gitignore and other ignore rules."
+                "Lists files in a given directory recursively as a tree, respecting gitignore and other ignore rules.
+Directories show their total file count. Directories deeper than `max_depth` are collapsed, and directories with a single entry are joined with it (e.g. `src/main.rs`).
+Very long listings are truncated; use `max_depth` or globs to narrow them down."
                     .to_string(),
             ),
             strict: Some(true),
@@ -52,10 +74,37 @@ impl Tool for ListFilesTool {
                     "path": {
                         "type": "string",
                         "description": "The path to the directory to list files from."
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "Only expand directories up to this depth. `1` lists only the direct entries. Unlimited by default."
+                    },
+                    "include": {
+                        "type": "array",
+                        "nullable": true,
+                        "items": { "type": "string" },
+                        "description": "Only list files matching one of these globs, e.g. `*.rs` or `src/**/*.ts`."
+                    },
+                    "exclude": {
+                        "type": "array",
+                        "nullable": true,
+                        "items": { "type": "string" },
+                        "description": "Skip files matching one of these globs."
+                    },
+                    "show_size": {
+                        "type": "boolean",
+                        "nullable": true,
+                        "description": "Show the size of each file. Defaults to false."
+                    },
+                    "show_line_count": {
+                        "type": "boolean",
+                        "nullable": true,
+                        "description": "Show the number of lines of each file. Defaults to false."
                     }
                 },
                 "additionalProperties": false,
-                "required": ["path"]
+                "required": ["path", "max_depth", "include", "exclude", "show_size", "show_line_count"]
             }),
         }
     }
@@ -99,20 +148,161 @@ pub fn execute_list_files(args: &ListFilesArgs, config: &Config) -> Result<Strin
     // Initial validation is now done in the planner.
     // We can proceed with the assumption that the path is a valid directory.
 
+    let filter = GlobFilter::new(
+        args.include.as_deref().unwrap_or_default(),
+        args.exclude.as_deref().unwrap_or_default(),
+    )?;
     let expansion_result =
         path_expander::expand_and_validate(&[args.path.clone()], &config.ignored_paths);
+    let files: Vec<&String> = expansion_result
+        .files
+        .iter()
+        .filter(|file| filter.matches(file))
+        .collect();
 
-    if expansion_result.files.is_empty() {
+    if files.is_empty() {
         return Ok(format!(
             "# No files found in '{}'. It might be empty or all files are ignored.",
             path_to_list.display()
         ));
     }
 
-    let header = format!("Files in `{}`:\n", path_to_list.display());
-    let file_list = expansion_result.files.join("\n");
+    let mut root = DirNode::default();
+    for file in &files {
+        let relative = Path::new(file.as_str())
+            .strip_prefix(path_to_list)
+            .unwrap_or(Path::new(file.as_str()));
+        let components: Vec<String> = relative
+            .iter()
+            .map(|c| c.to_string_lossy().to_string())
+            .collect();
+        root.insert(&components, file);
+    }
+
+    let mut entries = Vec::new();
+    root.render(args, 1, "", &mut entries);
+    let total = entries.len();
+    entries.truncate(MAX_LIST_ENTRIES);
+
+    // The metadata reads the files, so it is only added to the entries that are shown.
+    let mut lines: Vec<String> = entries
+        .into_iter()
+        .map(|(line, path)| match path {
+            Some(path) => format!("{line}{}", file_metadata(path, args)),
+            None => line,
+        })
+        .collect();
+
+    let header = format!(
+        "Files in `{}` ({} files):\n",
+        path_to_list.display(),
+        files.len()
+    );
+    if total > MAX_LIST_ENTRIES {
+        let truncated = total - MAX_LIST_ENTRIES;
+        lines.push(format!(
+            "[... truncated {truncated} entries, use `max_depth` or globs to narrow the listing]"
+        ));
+    }
+
+    Ok(format!("{header}{}", lines.join("\n")))
+}
 
-    Ok(format!("{header}{file_list}"))
+/// A directory in the listing tree.
+#[derive(Default)]
+struct DirNode {
+    dirs: BTreeMap<String, DirNode>,
+    /// The files directly in this directory, as `(name, full path)`.
+    files: Vec<(String, String)>,
+    /// The number of files in this directory and all its subdirectories.
+    file_count: usize,
+}
+
+impl DirNode {
+    fn insert(&mut self, components: &[String], full_path: &str) {
+        self.file_count += 1;
+        match components {
+            [] => {}
+            [name] => self.files.push((name.clone(), full_path.to_string())),
+            [dir, rest @ ..] => self
+                .dirs
+                .entry(dir.clone())
+                .or_default()
+                .insert(rest, full_path),
+        }
+    }
+
+    /// Renders the directory's entries, directories first, indented by two spaces per level.
+    /// File entries are paired with the file's full path, for their metadata.
+    fn render<'a>(
+        &'a self,
+        args: &ListFilesArgs,
+        depth: usize,
+        indent: &str,
+        lines: &mut Vec<(String, Option<&'a str>)>,
+    ) {
+        for (name, dir) in &self.dirs {
+            // Join chains of directories with a single entry, e.g. `src/main/java/`.
+            let mut label = name.clone();
+            let mut dir = dir;
+            while dir.files.is_empty() && dir.dirs.len() == 1 {
+                let (child_name, child) = dir.dirs.iter().next().unwrap();
+                label = format!("{label}/{child_name}");
+                dir = child;
+            }
+            if dir.dirs.is_empty() && dir.files.len() == 1 {
+                let (file_name, full_path) = &dir.files[0];
+                lines.push((format!("{indent}{label}/{file_name}"), Some(full_path)));
+                continue;
+            }
+
+            let count = dir.file_count;
+            let noun = if count == 1 { "file" } else { "files" };
+            lines.push((format!("{indent}{label}/ ({count} {noun})"), None));
+            if args.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                dir.render(args, depth + 1, &format!("{indent}  "), lines);
+            }
+        }
+        for (name, full_path) in &self.files {
+            lines.push((format!("{indent}{name}"), Some(full_path)));
+        }
+    }
+}
+
+/// Formats the optional size and line count of a file, e.g. ` (1.2 KB, 40 lines)`.
+fn file_metadata(path: &str, args: &ListFilesArgs) -> String {
+    let mut parts = Vec::new();
+    if args.show_size.unwrap_or(false)
+        && let Ok(metadata) = fs::metadata(path)
+    {
+        parts.push(format_size(metadata.len()));
+    }
+    if args.show_line_count.unwrap_or(false) {
+        match fs::read_to_string(path) {
+            Ok(content) => match content.lines().count() {
+                1 => parts.push("1 line".to_string()),
+                count => parts.push(format!("{count} lines")),
+            },
+            Err(_) => parts.push("binary".to_string()),
+        }
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join(", "))
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    let bytes_f = bytes as f64;
+    if bytes_f < KB {
+        format!("{bytes} B")
+    } else if bytes_f < KB * KB {
+        format!("{:.1} KB", bytes_f / KB)
+    } else {
+        format!("{:.1} MB", bytes_f / (KB * KB))
+    }
 }
 
 #[cfg(test)]
@@ -147,6 +337,7 @@ mod tests {
         let (tmp_dir, config) = setup_test_dir();
         let args = ListFilesArgs {
             path: tmp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
         };
 
         // Test planner
@@ -167,6 +358,7 @@ mod tests {
         let sub_dir_path = tmp_dir.path().join("sub_dir");
         let args = ListFilesArgs {
             path: sub_dir_path.to_str().unwrap().to_string(),
+            ..Default::default()
         };
 
         assert!(plan_list_files(&args, &config).is_ok());
@@ -186,6 +378,7 @@ mod tests {
 
         let args = ListFilesArgs {
             path: tmp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
         };
 
         let result = plan_list_files(&args, &config);
@@ -200,6 +393,7 @@ mod tests {
         let file_path = tmp_dir.path().join("file1.txt");
         let args = ListFilesArgs {
             path: file_path.to_str().unwrap().to_string(),
+            ..Default::default()
         };
 
         let result = plan_list_files(&args, &config);
@@ -220,6 +414,7 @@ mod tests {
 
         let args = ListFilesArgs {
             path: empty_dir_path.to_str().unwrap().to_string(),
+            ..Default::default()
         };
 
         assert!(plan_list_files(&args, &config).is_ok());
@@ -248,4 +443,92 @@ mod tests {
         assert!(preview_with_file.contains("test.txt"));
         assert!(preview_with_file.contains("Files in"));
     }
+
+    #[test]
+    fn test_tree_with_depth_globs_and_metadata() {
+        let (tmp_dir, config) = setup_test_dir();
+        let root = tmp_dir.path();
+        fs::create_dir_all(root.join("sub_dir/nested/deeper")).unwrap();
+        fs::write(root.join("sub_dir/nested/a.rs"), "fn a() {}\n").unwrap();
+        fs::write(root.join("sub_dir/nested/deeper/b.rs"), "fn b() {}\n").unwrap();
+        fs::write(root.join("sub_dir/three.rs"), "1\n2\n3\n").unwrap();
+        let path = root.to_str().unwrap().to_string();
+
+        let full = execute_list_files(
+            &ListFilesArgs {
+                path: path.clone(),
+                ..Default::default()
+            },
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            full.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "sub_dir/ (4 files)",
+                "  nested/ (2 files)",
+                "    deeper/b.rs",
+                "    a.rs",
+                "  file2.rs",
+                "  three.rs",
+                "file1.txt",
+            ]
+        );
+        assert!(full.starts_with(&format!("Files in `{path}` (5 files):")));
+
+        let shallow = execute_list_files(
+            &ListFilesArgs {
+                path: path.clone(),
+                max_depth: Some(1),
+                ..Default::default()
+            },
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            shallow.lines().skip(1).collect::<Vec<_>>(),
+            vec!["sub_dir/ (4 files)", "file1.txt"]
+        );
+
+        let filtered = execute_list_files(
+            &ListFilesArgs {
+                path,
+                include: Some(vec!["*.rs".to_string()]),
+                exclude: Some(vec!["**/nested/**".to_string()]),
+                show_size: Some(true),
+                show_line_count: Some(true),
+                ..Default::default()
+            },
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            filtered.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "sub_dir/ (2 files)",
+                "  file2.rs (8 B, 1 line)",
+                "  three.rs (6 B, 3 lines)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_listing_is_truncated() {
+        let (tmp_dir, config) = setup_test_dir();
+        let many = tmp_dir.path().join("many");
+        fs::create_dir(&many).unwrap();
+        for i in 0..MAX_LIST_ENTRIES + 10 {
+            fs::write(many.join(format!("file{i:04}.txt")), "x").unwrap();
+        }
+        let args = ListFilesArgs {
+            path: many.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let result = execute_list_files(&args, &config).unwrap();
+
+        assert!(result.ends_with(
+            "[... truncated 10 entries, use `max_depth` or globs to narrow the listing]"
+        ));
+    }
 }
@@ -0,0 +1,433 @@
+//! # Process Manager Tool
+//!
+//! This module provides the `manage_processes` tool, which lets the agent run long-lived
+//! commands (dev servers, watchers, REPLs) in the background and interact with them
+//! while continuing to use other tools.
+
+use crate::config::Config;
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::process_manager::{self, LogSlice, OutputStream, ProcessManager};
+use crate::tools::Tool;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use console::style;
+use openrouter_api::models::tool::FunctionDescription;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The number of log lines returned by the `logs` action if no limit is given.
+const DEFAULT_LOG_LIMIT: usize = 100;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessAction {
+    Start,
+    Status,
+    Logs,
+    SendInput,
+    Stop,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ProcessArgs {
+    pub action: ProcessAction,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub workdir: Option<String>,
+    #[serde(default)]
+    pub process_id: Option<usize>,
+    #[serde(default)]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl ProcessArgs {
+    fn required_process_id(&self) -> Result<usize> {
+        self.process_id.ok_or_else(|| {
+            anyhow!(
+                "`process_id` is required for the `{:?}` action.",
+                self.action
+            )
+        })
+    }
+
+    fn required_command(&self) -> Result<&str> {
+        self.command
+            .as_deref()
+            .filter(|c| !c.trim().is_empty())
+            .ok_or_else(|| anyhow!("`command` is required for the `start` action."))
+    }
+}
+
+/// The tool shares its `ProcessManager` with the `Agent`, which stops all
+/// processes when the session ends.
+pub struct ProcessTool {
+    processes: Arc<Mutex<ProcessManager>>,
+}
+
+impl ProcessTool {
+    pub fn new(processes: Arc<Mutex<ProcessManager>>) -> Self {
+        Self { processes }
+    }
+}
+
+#[async_trait]
+impl Tool for ProcessTool {
+    fn name(&self) -> &'static str {
+        "manage_processes"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "manage_processes".to_string(),
+            description: Some(
+                "Manages long-running background processes like dev servers, file watchers or REPLs.
+Use this instead of `execute_shell_command` for commands that do not exit on their own.
+
+**Actions**:
+- `start`: Starts `command` (optionally in `workdir`) and returns its `process_id`.
+- `status`: Lists all background processes, or only the one given by `process_id`.
+- `logs`: Returns output lines of a process. Without `offset`, returns the last `limit` lines. With `offset`, returns lines starting at that offset. Pass the returned `next_offset` to poll for new output only.
+- `send_input`: Writes `input` to the stdin of a process. A trailing newline is added if missing.
+- `stop`: Terminates a process and all of its children.
+
+All processes are stopped automatically when the session ends."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "enum": ["start", "status", "logs", "send_input", "stop"],
+                        "description": "The action to perform."
+                    },
+                    "command": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The shell command to start. Required for `start`."
+                    },
+                    "workdir": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The working directory for `start`. Defaults to the current working directory."
+                    },
+                    "process_id": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "The id of the process. Required for `logs`, `send_input` and `stop`."
+                    },
+                    "input": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The text to write to stdin. Required for `send_input`."
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "For `logs`: the offset of the first line to return."
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "For `logs`: the maximum number of lines to return. Defaults to 100."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["action", "command", "workdir", "process_id", "input", "offset", "limit"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: ProcessArgs = serde_json::from_value(args.clone())?;
+        match args.action {
+            ProcessAction::Start => {
+                let command = args.required_command()?;
+                let mut output = vec![];
+                if let Some(workdir) = &args.workdir {
+                    if permissions::is_path_accessible(Path::new(workdir), &config.accessible_paths)
+                        .is_err()
+                    {
+                        return Err(anyhow!(
+                            "Cannot preview: working directory '{workdir}' is not accessible"
+                        ));
+                    }
+                    output.push(format!("Workdir: {workdir}"));
+                }
+                output.push(format!("Start in background: $ {}", style(command).bold()));
+                Ok(output.join("\n"))
+            }
+            ProcessAction::Status => match args.process_id {
+                Some(id) => Ok(format!("Show status of process {id}")),
+                None => Ok("Show status of all background processes".to_string()),
+            },
+            ProcessAction::Logs => {
+                let id = args.required_process_id()?;
+                let manager = self.processes.lock().unwrap();
+                let process = manager.get(id)?;
+                Ok(format!("Read logs of process {id}: `{}`", process.command))
+            }
+            ProcessAction::SendInput => {
+                let id = args.required_process_id()?;
+                let input = args
+                    .input
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("`input` is required for the `send_input` action."))?;
+                let manager = self.processes.lock().unwrap();
+                let process = manager.get(id)?;
+                Ok(format!(
+                    "Send input to process {id} (`{}`):\n{}",
+                    process.command,
+                    style(input).bold()
+                ))
+            }
+            ProcessAction::Stop => {
+                let id = args.required_process_id()?;
+                let manager = self.processes.lock().unwrap();
+                let process = manager.get(id)?;
+                Ok(format!("Stop process {id}: `{}`", process.command))
+            }
+        }
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: ProcessArgs = serde_json::from_value(args.clone())?;
+        match args.action {
+            ProcessAction::Start => {
+                let command = args.required_command()?;
+                let env = permissions::command_env(config);
+                let mut manager = self.processes.lock().unwrap();
+                let process = manager.start(command, args.workdir.as_deref(), &env)?;
+                let pid = process
+                    .pid
+                    .map(|pid| format!(" (pid {pid})"))
+                    .unwrap_or_default();
+                Ok(format!(
+                    "Started process {}{pid}: `{}`\nUse the `logs` action to check its output.",
+                    process.id, process.command
+                ))
+            }
+            ProcessAction::Status => {
+                let manager = self.processes.lock().unwrap();
+                match args.process_id {
+                    Some(id) => Ok(format_status_line(manager.get(id)?)),
+                    None => {
+                        let lines: Vec<String> = manager.list().map(format_status_line).collect();
+                        if lines.is_empty() {
+                            Ok("No background processes.".to_string())
+                        } else {
+                            Ok(lines.join("\n"))
+                        }
+                    }
+                }
+            }
+            ProcessAction::Logs => {
+                let id = args.required_process_id()?;
+                let manager = self.processes.lock().unwrap();
+                let process = manager.get(id)?;
+                let slice = process.read_logs(args.offset, args.limit.unwrap_or(DEFAULT_LOG_LIMIT));
+                Ok(format_logs(id, &process.describe_state(), &slice))
+            }
+            ProcessAction::SendInput => {
+                let id = args.required_process_id()?;
+                let mut input = args
+                    .input
+                    .ok_or_else(|| anyhow!("`input` is required for the `send_input` action."))?;
+                if !input.ends_with('\n') {
+                    input.push('\n');
+                }
+                self.processes.lock().unwrap().send_input(id, &input)?;
+                Ok(format!("Sent {} bytes to process {id}.", input.len()))
+            }
+            ProcessAction::Stop => {
+                let id = args.required_process_id()?;
+                // The lock must not be held while waiting for the process to exit.
+                let exit_status = self.processes.lock().unwrap().request_stop(id)?;
+                let status = match process_manager::wait_for_exit(exit_status.clone()).await {
+                    Some(status) => status,
+                    None => {
+                        self.processes.lock().unwrap().force_kill(id);
+                        process_manager::wait_for_exit(exit_status)
+                            .await
+                            .ok_or_else(|| anyhow!("Process {id} could not be stopped."))?
+                    }
+                };
+                Ok(format!(
+                    "Process {id} stopped ({}).",
+                    process_manager::describe_exit_status(&status)
+                ))
+            }
+        }
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        let args: ProcessArgs = serde_json::from_value(args.clone())?;
+        match args.action {
+            ProcessAction::Start => {
+                let command = args.required_command()?;
+                if permissions::is_command_allowed(command, &config.allowed_command_prefixes)
+                    .is_err()
+                {
+                    return Ok(false);
+                }
+                if let Some(workdir) = &args.workdir
+                    && permissions::is_path_accessible(Path::new(workdir), &config.accessible_paths)
+                        .is_err()
+                {
+                    return Ok(false);
+                }
+                Ok(true)
+            }
+            ProcessAction::Status | ProcessAction::Logs | ProcessAction::Stop => Ok(true),
+            // The input is interpreted by an arbitrary program, so always ask the user.
+            ProcessAction::SendInput => Ok(false),
+        }
+    }
+}
+
+fn format_status_line(process: &process_manager::BackgroundProcess) -> String {
+    format!(
+        "Process {}: {} after {}s | `{}`",
+        process.id,
+        process.describe_state(),
+        process.uptime().as_secs(),
+        process.command
+    )
+}
+
+fn format_logs(id: usize, state: &str, slice: &LogSlice) -> String {
+    let mut output = vec![format!(
+        "Process {id} ({state}) | Lines {}-{} | next_offset: {}",
+        slice.start_offset, slice.next_offset, slice.next_offset
+    )];
+    if slice.evicted > 0 {
+        output.push(format!(
+            "[{} older lines were dropped from the log buffer]",
+            slice.evicted
+        ));
+    }
+    if slice.lines.is_empty() {
+        output.push("[No new output]".to_string());
+    }
+    for line in &slice.lines {
+        match line.stream {
+            OutputStream::Stdout => output.push(line.text.clone()),
+            OutputStream::Stderr => output.push(format!("[stderr] {}", line.text)),
+        }
+    }
+    output.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn setup() -> (ProcessTool, Config, Arc<Mutex<FileStateManager>>) {
+        let processes = Arc::new(Mutex::new(ProcessManager::new(100)));
+        (
+            ProcessTool::new(processes),
+            Config::default(),
+            Arc::new(Mutex::new(FileStateManager::new())),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_start_logs_and_stop() {
+        let (tool, config, fsm) = setup();
+        let start = serde_json::json!({ "action": "start", "command": "echo ready; sleep 30" });
+        let result = tool.execute(&start, &config, fsm.clone()).await.unwrap();
+        assert!(result.starts_with("Started process 1"));
+
+        let logs = serde_json::json!({ "action": "logs", "process_id": 1 });
+        let mut output = String::new();
+        for _ in 0..100 {
+            output = tool.execute(&logs, &config, fsm.clone()).await.unwrap();
+            if output.contains("ready") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(output.contains("Process 1 (running)"));
+        assert!(output.contains("next_offset: 1"));
+        assert!(output.contains("ready"));
+
+        let stop = serde_json::json!({ "action": "stop", "process_id": 1 });
+        let result = tool.execute(&stop, &config, fsm.clone()).await.unwrap();
+        assert!(result.contains("Process 1 stopped"));
+
+        let status = serde_json::json!({ "action": "status" });
+        let result = tool.execute(&status, &config, fsm).await.unwrap();
+        assert!(!result.contains("running"));
+    }
+
+    #[test]
+    fn test_format_logs_marks_stderr_and_evictions() {
+        let slice = LogSlice {
+            start_offset: 5,
+            lines: vec![
+                process_manager::LogLine {
+                    stream: OutputStream::Stdout,
+                    text: "listening".to_string(),
+                },
+                process_manager::LogLine {
+                    stream: OutputStream::Stderr,
+                    text: "warning".to_string(),
+                },
+            ],
+            next_offset: 7,
+            evicted: 5,
+        };
+        let output = format_logs(3, "running", &slice);
+        assert_eq!(
+            output,
+            "Process 3 (running) | Lines 5-7 | next_offset: 7\n[5 older lines were dropped from the log buffer]\nlistening\n[stderr] warning"
+        );
+    }
+
+    #[test]
+    fn test_auto_execute_safety() {
+        let (tool, config, _fsm) = setup();
+        let allowed = serde_json::json!({ "action": "start", "command": "ls -la" });
+        let disallowed = serde_json::json!({ "action": "start", "command": "npm run dev" });
+        let input = serde_json::json!({ "action": "send_input", "process_id": 1, "input": "q" });
+        let logs = serde_json::json!({ "action": "logs", "process_id": 1 });
+
+        assert!(tool.is_safe_for_auto_execute(&allowed, &config).unwrap());
+        assert!(!tool.is_safe_for_auto_execute(&disallowed, &config).unwrap());
+        assert!(!tool.is_safe_for_auto_execute(&input, &config).unwrap());
+        assert!(tool.is_safe_for_auto_execute(&logs, &config).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_missing_arguments() {
+        let (tool, config, fsm) = setup();
+        let args = serde_json::json!({ "action": "stop" });
+        let result = tool.execute(&args, &config, fsm).await;
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("`process_id` is required")
+        );
+    }
+}
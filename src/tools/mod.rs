@@ -11,16 +11,32 @@ use openrouter_api::models::tool::FunctionDescription;
 use serde_json::Value;
 use std::sync::{Arc, Mutex};
 
+pub mod check;
 pub mod create_files;
 pub mod edit_files;
 pub mod execute_shell_command;
+pub mod fetch_url;
+pub mod file_outline;
+pub mod git;
+pub mod language_server;
 pub mod list_files;
+pub mod manage_processes;
 pub mod read_files;
+pub mod run_tests;
+pub mod search_files;
+pub use self::check::CheckTool;
 pub use self::create_files::FileCreatorTool;
 pub use self::edit_files::FileEditorTool;
 pub use self::execute_shell_command::ShellTool;
+pub use self::fetch_url::FetchUrlTool;
+pub use self::file_outline::FileOutlineTool;
+pub use self::git::GitTool;
+pub use self::language_server::LanguageServerTool;
 pub use self::list_files::ListFilesTool;
+pub use self::manage_processes::ProcessTool;
 pub use self::read_files::FileReaderTool;
+pub use self::run_tests::RunTestsTool;
+pub use self::search_files::SearchFilesTool;
 
 /// A trait representing a self-contained, executable tool.
 ///
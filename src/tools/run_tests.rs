@@ -0,0 +1,494 @@
+//! # Test Runner Tool
+//!
+//! This module provides the `run_tests` tool, which runs the configured test command
+//! (cargo's libtest output is understood) and returns a structured summary: the number
+//! of passed, failed and ignored tests, and for each failure its panic message and
+//! the failing line in LIF form.
+
+use crate::config::Config;
+use crate::diagnostics::{self, Diagnostic, Severity};
+use crate::file_state_manager::FileStateManager;
+use crate::permissions;
+use crate::tools::Tool;
+use crate::tools::check::parse_diagnostics;
+use crate::tools::execute_shell_command::{capture_shell_command, plan_workdir};
+use anyhow::Result;
+use async_trait::async_trait;
+use console::style;
+use once_cell::sync::Lazy;
+use openrouter_api::models::tool::FunctionDescription;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::NoneAsEmptyString;
+use serde_with::serde_as;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Long panic messages (e.g. huge `assert_eq!` diffs) are cut after this many lines.
+const MAX_PANIC_MESSAGE_LINES: usize = 20;
+
+#[serde_as]
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RunTestsArgs {
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub filter: Option<String>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub workdir: Option<String>,
+}
+
+impl RunTestsArgs {
+    /// The full command line, with the filter appended as a single shell word.
+    fn command(&self, config: &Config) -> String {
+        match &self.filter {
+            Some(filter) => format!(
+                "{} '{}'",
+                config.test_command,
+                filter.replace('\'', r"'\''")
+            ),
+            None => config.test_command.clone(),
+        }
+    }
+}
+
+pub struct RunTestsTool;
+
+#[async_trait]
+impl Tool for RunTestsTool {
+    fn name(&self) -> &'static str {
+        "run_tests"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "run_tests".to_string(),
+            description: Some(
+                "Runs the project's configured test command (e.g. `cargo test`) and returns a summary with the number of passed, failed and ignored tests.
+For each failed test, its name, panic message and failing location are listed. Failing lines are shown in LIF form with their LIDs, ready for `edit_files`.
+If the tests do not compile, the compiler errors are returned instead.
+Prefer this tool over running the tests through `execute_shell_command`."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "filter": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "Only run tests whose name contains this string. Runs all tests if omitted."
+                    },
+                    "workdir": {
+                        "type": "string",
+                        "nullable": true,
+                        "description": "The directory to run the tests in. Defaults to the current working directory."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["filter", "workdir"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: RunTestsArgs = serde_json::from_value(args.clone())?;
+        plan_workdir(args.workdir.as_deref(), config)?;
+        let mut output = vec![];
+        if let Some(workdir) = &args.workdir {
+            output.push(format!("Workdir: {workdir}"));
+        }
+        output.push(format!("$ {}", style(args.command(config)).bold()));
+        Ok(output.join("\n"))
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: RunTestsArgs = serde_json::from_value(args.clone())?;
+        plan_workdir(args.workdir.as_deref(), config)?;
+        execute_run_tests(&args, config, fsm).await
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        // The command itself comes from the user's configuration, so only the location matters.
+        let args: RunTestsArgs = serde_json::from_value(args.clone())?;
+        Ok(plan_workdir(args.workdir.as_deref(), config).is_ok())
+    }
+}
+
+/// A test that failed, as reported by libtest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestFailure {
+    pub name: String,
+    pub message: String,
+    /// The panic location as `(file, line, column)`.
+    pub location: Option<(String, usize, usize)>,
+}
+
+/// The parsed results of a test run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub failures: Vec<TestFailure>,
+    /// Whether any libtest output was found at all.
+    pub recognized: bool,
+}
+
+static TEST_RESULT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^test result: \w+\. (?P<passed>\d+) passed; (?P<failed>\d+) failed; (?P<ignored>\d+) ignored")
+        .unwrap()
+});
+
+static TEST_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^test (?P<name>\S+)(?: - .*)? \.\.\. (?P<outcome>ok|FAILED|ignored)").unwrap()
+});
+
+static FAILURE_HEADER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^---- (?P<name>\S+) stdout ----$").unwrap());
+
+/// Matches `thread 'name' panicked at src/lib.rs:10:5:` (Rust 1.73+), with the message on the
+/// following lines, as well as the older `thread 'name' panicked at 'message', src/lib.rs:10:5`.
+static PANIC_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^thread '.*' panicked at (?:'(?P<old_message>.*)', )?(?P<file>[^:\s]+):(?P<line>\d+):(?P<col>\d+):?$",
+    )
+    .unwrap()
+});
+
+/// Adds a completed failure to the summary, truncating overly long panic messages.
+fn push_failure(failure: Option<TestFailure>, summary: &mut TestSummary) {
+    if let Some(mut failure) = failure {
+        let lines: Vec<&str> = failure.message.lines().collect();
+        if lines.len() > MAX_PANIC_MESSAGE_LINES {
+            failure.message = format!(
+                "{}\n[... {} more lines]",
+                lines[..MAX_PANIC_MESSAGE_LINES].join("\n"),
+                lines.len() - MAX_PANIC_MESSAGE_LINES
+            );
+        }
+        summary.failures.push(failure);
+    }
+}
+
+/// Parses libtest's human-readable output. The output of multiple test binaries is summed up.
+pub fn parse_libtest_output(output: &str) -> TestSummary {
+    let mut summary = TestSummary::default();
+    let mut line_counts = (0, 0, 0);
+    let mut found_result_line = false;
+    let mut current: Option<TestFailure> = None;
+    let mut in_panic_message = false;
+
+    for line in output.lines() {
+        if let Some(caps) = TEST_RESULT_RE.captures(line) {
+            found_result_line = true;
+            summary.passed += caps["passed"].parse::<usize>().unwrap_or(0);
+            summary.failed += caps["failed"].parse::<usize>().unwrap_or(0);
+            summary.ignored += caps["ignored"].parse::<usize>().unwrap_or(0);
+            continue;
+        }
+        if let Some(caps) = TEST_LINE_RE.captures(line) {
+            summary.recognized = true;
+            match &caps["outcome"] {
+                "ok" => line_counts.0 += 1,
+                "FAILED" => line_counts.1 += 1,
+                _ => line_counts.2 += 1,
+            }
+            continue;
+        }
+        if let Some(caps) = FAILURE_HEADER_RE.captures(line) {
+            push_failure(current.take(), &mut summary);
+            current = Some(TestFailure {
+                name: caps["name"].to_string(),
+                message: String::new(),
+                location: None,
+            });
+            in_panic_message = false;
+            continue;
+        }
+        let Some(failure) = current.as_mut() else {
+            continue;
+        };
+        if line == "failures:" || line.starts_with("test result:") {
+            push_failure(current.take(), &mut summary);
+            continue;
+        }
+        if failure.location.is_none()
+            && let Some(caps) = PANIC_RE.captures(line)
+        {
+            failure.location = Some((
+                caps["file"].to_string(),
+                caps["line"].parse().unwrap_or(1),
+                caps["col"].parse().unwrap_or(1),
+            ));
+            match caps.name("old_message") {
+                Some(message) => failure.message = message.as_str().to_string(),
+                None => in_panic_message = true,
+            }
+            continue;
+        }
+        if in_panic_message {
+            if line.is_empty() || line.starts_with("note: ") || line == "stack backtrace:" {
+                in_panic_message = false;
+            } else {
+                if !failure.message.is_empty() {
+                    failure.message.push('\n');
+                }
+                failure.message.push_str(line);
+            }
+        }
+    }
+    push_failure(current.take(), &mut summary);
+
+    summary.recognized |= found_result_line;
+    if !found_result_line {
+        // The run was probably aborted; fall back to the per-test lines that were printed.
+        (summary.passed, summary.failed, summary.ignored) = line_counts;
+    }
+    summary
+}
+
+pub async fn execute_run_tests(
+    args: &RunTestsArgs,
+    config: &Config,
+    fsm: Arc<Mutex<FileStateManager>>,
+) -> Result<String> {
+    let command = args.command(config);
+    println!("$ {}", style(&command).bold());
+    let env = permissions::command_env(config);
+    let result = capture_shell_command(&command, args.workdir.as_deref(), &env).await?;
+    let base_dir = Path::new(args.workdir.as_deref().unwrap_or("."));
+
+    let summary = parse_libtest_output(&result.stdout);
+    let mut output = vec![format!("$ {command}")];
+
+    if !summary.recognized {
+        // Most likely the tests did not compile.
+        let compile_errors: Vec<Diagnostic> = parse_diagnostics(&result.stdout, &result.stderr)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .collect();
+        let headline = format!("{} | No test results found", result.exit_message());
+        println!("{}", style(&headline).red().bold());
+        output.push(headline);
+        output.push(String::new());
+        if compile_errors.is_empty() {
+            output.push(result.output_tail());
+        } else {
+            let mut manager = fsm.lock().unwrap();
+            output.push(diagnostics::render_diagnostics(
+                &compile_errors,
+                base_dir,
+                config,
+                &mut manager,
+            ));
+        }
+        return Ok(output.join("\n"));
+    }
+
+    let headline = format!(
+        "{} | {} passed, {} failed, {} ignored",
+        result.exit_message(),
+        summary.passed,
+        summary.failed,
+        summary.ignored
+    );
+    if summary.failures.is_empty() {
+        println!("{}", style(&headline).green().bold());
+    } else {
+        println!("{}", style(&headline).red().bold());
+        for failure in &summary.failures {
+            println!("{} {}", style("FAILED").red(), failure.name);
+        }
+    }
+    output.push(headline);
+
+    if !summary.failures.is_empty() {
+        output.push(String::new());
+        output.push("Failed tests:".to_string());
+        for failure in &summary.failures {
+            let location = failure
+                .location
+                .as_ref()
+                .map(|(file, line, col)| format!(" (at {file}:{line}:{col})"))
+                .unwrap_or_default();
+            output.push(format!("- {}{location}", failure.name));
+            for message_line in failure.message.lines() {
+                output.push(format!("  {message_line}"));
+            }
+        }
+
+        let locations: Vec<Diagnostic> = summary
+            .failures
+            .iter()
+            .filter_map(|failure| {
+                let (file, line, column) = failure.location.clone()?;
+                Some(Diagnostic {
+                    file,
+                    line,
+                    end_line: line,
+                    column,
+                    severity: Severity::Error,
+                    code: None,
+                    message: format!("`{}` panicked here", failure.name),
+                })
+            })
+            .collect();
+        if !locations.is_empty() {
+            let mut manager = fsm.lock().unwrap();
+            output.push(String::new());
+            output.push(diagnostics::render_diagnostics(
+                &locations,
+                base_dir,
+                config,
+                &mut manager,
+            ));
+        }
+    }
+    Ok(output.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    const LIBTEST_OUTPUT: &str = "
+running 4 tests
+test tests::adds ... ok
+test tests::ignored_one ... ignored
+test tests::compares ... FAILED
+test tests::old_style ... FAILED
+
+failures:
+
+---- tests::compares stdout ----
+
+thread 'tests::compares' panicked at src/lib.rs:12:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+---- tests::old_style stdout ----
+thread 'tests::old_style' panicked at 'boom', src/other.rs:3:5
+
+
+failures:
+    tests::compares
+    tests::old_style
+
+test result: FAILED. 1 passed; 2 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+running 1 test
+test it_works ... ok
+
+test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+
+    #[test]
+    fn test_parse_libtest_output() {
+        let summary = parse_libtest_output(LIBTEST_OUTPUT);
+        assert!(summary.recognized);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (2, 2, 1));
+        assert_eq!(
+            summary.failures,
+            vec![
+                TestFailure {
+                    name: "tests::compares".to_string(),
+                    message: "assertion `left == right` failed\n  left: 1\n right: 2".to_string(),
+                    location: Some(("src/lib.rs".to_string(), 12, 9)),
+                },
+                TestFailure {
+                    name: "tests::old_style".to_string(),
+                    message: "boom".to_string(),
+                    location: Some(("src/other.rs".to_string(), 3, 5)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_is_quoted() {
+        let config = Config::default();
+        let args = RunTestsArgs {
+            filter: Some("it's".to_string()),
+            workdir: None,
+        };
+        assert_eq!(
+            args.command(&config),
+            format!("{} 'it'\\''s'", config.test_command)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_mapped_to_lids() {
+        let tmp_dir = Builder::new().prefix("test-run-tests-").tempdir().unwrap();
+        let workdir = tmp_dir.path().to_str().unwrap().to_string();
+        fs::create_dir(tmp_dir.path().join("src")).unwrap();
+        let source = (1..=12)
+            .map(|i| format!("line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(tmp_dir.path().join("src/lib.rs"), source).unwrap();
+        fs::write(tmp_dir.path().join("output.txt"), LIBTEST_OUTPUT).unwrap();
+        let config = Config {
+            accessible_paths: vec![workdir.clone()],
+            test_command: "cat output.txt; exit 101".to_string(),
+            ..Default::default()
+        };
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let args = RunTestsArgs {
+            filter: None,
+            workdir: Some(workdir),
+        };
+
+        let output = execute_run_tests(&args, &config, fsm.clone())
+            .await
+            .unwrap();
+
+        let lid = fsm
+            .lock()
+            .unwrap()
+            .open_file(tmp_dir.path().join("src/lib.rs").to_str().unwrap())
+            .unwrap()
+            .lid_at_line(12)
+            .unwrap();
+        assert!(output.contains("Exit code: 101 | 2 passed, 2 failed, 1 ignored"));
+        assert!(output.contains("- tests::compares (at src/lib.rs:12:9)\n  assertion"));
+        assert!(output.contains(&format!(
+            "  error 12:9 {lid}: `tests::compares` panicked here"
+        )));
+        assert!(output.contains("src/other.rs: 1 error, 0 warnings\n  error 3:5: `tests::old_style` panicked here\n[File not accessible"));
+    }
+
+    #[tokio::test]
+    async fn test_compile_errors_are_reported() {
+        let config = Config {
+            test_command: "echo 'src/lib.rs:1:1: error: expected item' >&2; exit 101".to_string(),
+            ..Default::default()
+        };
+        let fsm = Arc::new(Mutex::new(FileStateManager::new()));
+        let args = RunTestsArgs {
+            filter: None,
+            workdir: None,
+        };
+
+        let output = execute_run_tests(&args, &config, fsm).await.unwrap();
+
+        assert!(output.contains("Exit code: 101 | No test results found"));
+        assert!(output.contains("error 1:1"));
+        assert!(output.contains("expected item"));
+    }
+}
@@ -0,0 +1,374 @@
+//! # Search Tool
+//!
+//! This module provides the `search_files` tool, a native replacement for running
+//! `rg` through the shell. Matches are returned in LIF form, so the LLM can edit
+//! them without reading the files first.
+
+use crate::config::Config;
+use crate::file_state::RangeSpec;
+use crate::file_state_manager::FileStateManager;
+use crate::path_expander::{self, GlobFilter};
+use crate::permissions;
+use crate::tools::Tool;
+use crate::tools::read_files::merge_ranges;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use console::style;
+use openrouter_api::models::tool::FunctionDescription;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The maximum number of matching lines returned by a single search.
+const MAX_MATCHES: usize = 100;
+
+const DEFAULT_CONTEXT_LINES: usize = 2;
+
+/// Validates the arguments for a search.
+/// Ensures the pattern and globs are valid and all search paths are accessible.
+fn plan_search(args: &SearchFilesArgs, config: &Config) -> Result<(Regex, GlobFilter)> {
+    if args.pattern.is_empty() {
+        return Err(anyhow!("The search pattern must not be empty."));
+    }
+    for path in args.search_paths() {
+        permissions::is_path_accessible(Path::new(&path), &config.accessible_paths)?;
+    }
+    let pattern = if args.is_regex.unwrap_or(false) {
+        args.pattern.clone()
+    } else {
+        regex::escape(&args.pattern)
+    };
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(!args.case_sensitive.unwrap_or(true))
+        .build()
+        .map_err(|e| anyhow!("Invalid regex '{}': {e}", args.pattern))?;
+    let filter = GlobFilter::new(
+        args.include.as_deref().unwrap_or_default(),
+        args.exclude.as_deref().unwrap_or_default(),
+    )?;
+    Ok((regex, filter))
+}
+
+#[derive(Deserialize, Debug, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct SearchFilesArgs {
+    pub pattern: String,
+    pub is_regex: Option<bool>,
+    pub case_sensitive: Option<bool>,
+    pub paths: Option<Vec<String>>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub context_lines: Option<usize>,
+}
+
+impl SearchFilesArgs {
+    fn search_paths(&self) -> Vec<String> {
+        match &self.paths {
+            Some(paths) if !paths.is_empty() => paths.clone(),
+            _ => vec![".".to_string()],
+        }
+    }
+}
+
+pub struct SearchFilesTool;
+
+#[async_trait]
+impl Tool for SearchFilesTool {
+    fn name(&self) -> &'static str {
+        "search_files"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "search_files".to_string(),
+            description: Some(
+                r#"Searches file contents for a literal string or a regex, respecting gitignore and other ignore rules.
+Matches are returned with surrounding context lines in LIF form, including the file hash and the LID of every line. Use these directly with `edit_files`, there is no need to read the files again.
+Prefer this tool over running `rg` or `grep` through `execute_shell_command`.
+
+Example tool call:
+{
+  "pattern": "fn prepare_request",
+  "is_regex": false,
+  "case_sensitive": null,
+  "paths": ["src"],
+  "include": ["*.rs"],
+  "exclude": null,
+  "context_lines": 3
+}"#
+                .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "The text or regex to search for. Matched against single lines."
+                    },
+                    "is_regex": {
+                        "type": "boolean",
+                        "nullable": true,
+                        "description": "Whether `pattern` is a regex (Rust syntax). Defaults to false, i.e. a literal search."
+                    },
+                    "case_sensitive": {
+                        "type": "boolean",
+                        "nullable": true,
+                        "description": "Whether the search is case sensitive. Defaults to true."
+                    },
+                    "paths": {
+                        "type": "array",
+                        "nullable": true,
+                        "items": { "type": "string" },
+                        "description": "Files or directories to search. Defaults to the current working directory."
+                    },
+                    "include": {
+                        "type": "array",
+                        "nullable": true,
+                        "items": { "type": "string" },
+                        "description": "Only search files matching one of these globs, e.g. `*.rs` or `src/**/*.ts`."
+                    },
+                    "exclude": {
+                        "type": "array",
+                        "nullable": true,
+                        "items": { "type": "string" },
+                        "description": "Skip files matching one of these globs."
+                    },
+                    "context_lines": {
+                        "type": "integer",
+                        "nullable": true,
+                        "description": "The number of lines to show before and after each match. Defaults to 2."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["pattern", "is_regex", "case_sensitive", "paths", "include", "exclude", "context_lines"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: SearchFilesArgs = serde_json::from_value(args.clone())?;
+        plan_search(&args, config)?;
+        let kind = if args.is_regex.unwrap_or(false) {
+            "regex"
+        } else {
+            "text"
+        };
+        let mut preview = format!(
+            "Search for {kind} {} in {}",
+            style(&args.pattern).bold(),
+            args.search_paths().join(", ")
+        );
+        if let Some(include) = args.include.as_ref().filter(|globs| !globs.is_empty()) {
+            preview.push_str(&format!("\nInclude: {}", include.join(", ")));
+        }
+        if let Some(exclude) = args.exclude.as_ref().filter(|globs| !globs.is_empty()) {
+            preview.push_str(&format!("\nExclude: {}", exclude.join(", ")));
+        }
+        Ok(preview)
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: SearchFilesArgs = serde_json::from_value(args.clone())?;
+        let mut manager = fsm.lock().unwrap();
+        execute_search_files(&args, config, &mut manager)
+    }
+
+    fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
+        let args: SearchFilesArgs = serde_json::from_value(args.clone())?;
+        Ok(args.search_paths().iter().all(|path| {
+            permissions::is_path_accessible(Path::new(path), &config.accessible_paths).is_ok()
+        }))
+    }
+}
+
+pub fn execute_search_files(
+    args: &SearchFilesArgs,
+    config: &Config,
+    file_state_manager: &mut FileStateManager,
+) -> Result<String> {
+    let (regex, filter) = plan_search(args, config)?;
+    let context_lines = args.context_lines.unwrap_or(DEFAULT_CONTEXT_LINES);
+
+    let expansion = path_expander::expand_and_validate(&args.search_paths(), &config.ignored_paths);
+    if !expansion.not_found.is_empty() {
+        return Err(anyhow!(
+            "Paths not found: {}",
+            expansion.not_found.join(", ")
+        ));
+    }
+
+    let mut sections = Vec::new();
+    let mut total_matches = 0;
+    let mut shown_matches = 0;
+    let mut matched_files = 0;
+
+    for file in expansion.files.iter().filter(|file| filter.matches(file)) {
+        // Binary and non-UTF-8 files are skipped. Reading the content directly, instead of
+        // through the manager, avoids caching a `FileState` for every file in the tree.
+        let Ok(content) = fs::read_to_string(file) else {
+            continue;
+        };
+        let matching_lines: Vec<usize> = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| regex.is_match(line))
+            .map(|(i, _)| i + 1)
+            .collect();
+        if matching_lines.is_empty() {
+            continue;
+        }
+        matched_files += 1;
+        total_matches += matching_lines.len();
+
+        let remaining = MAX_MATCHES - shown_matches;
+        if remaining == 0 {
+            continue;
+        }
+        let shown: Vec<usize> = matching_lines.into_iter().take(remaining).collect();
+        shown_matches += shown.len();
+
+        let file_state = file_state_manager.open_file(file)?;
+        let ranges = merge_ranges(
+            shown
+                .iter()
+                .map(|&line| RangeSpec {
+                    start_line: line.saturating_sub(context_lines).max(1),
+                    end_line: line + context_lines,
+                })
+                .collect(),
+        );
+        let line_list = shown
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        sections.push(format!(
+            "Matches on lines {line_list}:\n{}",
+            file_state.display_lif_contents_for_ranges(Some(&ranges))
+        ));
+    }
+
+    if total_matches == 0 {
+        return Ok(format!("# No matches found for '{}'.", args.pattern));
+    }
+
+    let mut header = format!("Found {total_matches} matching lines in {matched_files} files.");
+    if shown_matches < total_matches {
+        header.push_str(&format!(
+            " Showing the first {shown_matches}, narrow the search to see more."
+        ));
+    }
+    Ok(format!("{header}\n\n{}", sections.join("\n\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    fn setup_test_dir() -> (tempfile::TempDir, Config) {
+        let tmp_dir = Builder::new()
+            .prefix("test-search-files")
+            .tempdir()
+            .unwrap();
+        let root = tmp_dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(
+            root.join("src/main.rs"),
+            "fn main() {\n    let x = helper();\n    println!(\"{x}\");\n}\n\nfn helper() -> u8 {\n    42\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("notes.txt"),
+            "call helper() later\nHELPER in caps\n",
+        )
+        .unwrap();
+        fs::create_dir(root.join("ignored_dir")).unwrap();
+        fs::write(root.join("ignored_dir/copy.rs"), "fn helper() {}\n").unwrap();
+
+        let config = Config {
+            accessible_paths: vec![root.to_str().unwrap().to_string()],
+            ignored_paths: vec!["ignored_dir/".to_string()],
+            ..Default::default()
+        };
+        (tmp_dir, config)
+    }
+
+    fn args(root: &Path, pattern: &str) -> SearchFilesArgs {
+        SearchFilesArgs {
+            pattern: pattern.to_string(),
+            paths: Some(vec![root.to_str().unwrap().to_string()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_literal_search_returns_lif_with_context() {
+        let (tmp_dir, config) = setup_test_dir();
+        let mut fsm = FileStateManager::new();
+        let mut args = args(tmp_dir.path(), "helper()");
+        args.context_lines = Some(1);
+
+        let result = execute_search_files(&args, &config, &mut fsm).unwrap();
+
+        assert!(result.starts_with("Found 3 matching lines in 2 files."));
+        assert!(result.contains("Matches on lines 2, 6:"));
+        assert!(result.contains("Matches on lines 1:"));
+        assert!(!result.contains("ignored_dir"));
+
+        let main_rs = tmp_dir.path().join("src/main.rs");
+        let state = fsm.open_file(main_rs.to_str().unwrap()).unwrap();
+        assert!(result.contains(&format!("Hash: {}", state.get_short_hash())));
+        assert!(result.contains("| Lines: 1-3, 5-7/8"));
+        let lid = state.lid_at_line(6).unwrap();
+        assert!(result.contains(&format!("6    {lid}: fn helper() -> u8 {{")));
+    }
+
+    #[test]
+    fn test_regex_globs_and_case_insensitivity() {
+        let (tmp_dir, config) = setup_test_dir();
+        let mut fsm = FileStateManager::new();
+
+        let mut regex_args = args(tmp_dir.path(), r"fn \w+\(\)");
+        regex_args.is_regex = Some(true);
+        regex_args.include = Some(vec!["*.rs".to_string()]);
+        let result = execute_search_files(&regex_args, &config, &mut fsm).unwrap();
+        assert!(result.starts_with("Found 2 matching lines in 1 files."));
+        assert!(!result.contains("notes.txt"));
+
+        let mut case_args = args(tmp_dir.path(), "helper");
+        case_args.case_sensitive = Some(false);
+        case_args.exclude = Some(vec!["*.rs".to_string()]);
+        let result = execute_search_files(&case_args, &config, &mut fsm).unwrap();
+        assert!(result.starts_with("Found 2 matching lines in 1 files."));
+
+        let none = execute_search_files(&args(tmp_dir.path(), "nothing"), &config, &mut fsm);
+        assert!(none.unwrap().contains("No matches found"));
+    }
+
+    #[test]
+    fn test_inaccessible_path_is_rejected() {
+        let (tmp_dir, mut config) = setup_test_dir();
+        config.accessible_paths = vec!["/some/other/path".to_string()];
+        let args = args(tmp_dir.path(), "helper");
+
+        assert!(plan_search(&args, &config).is_err());
+        let tool = SearchFilesTool;
+        let value = serde_json::to_value(&args).unwrap();
+        assert!(!tool.is_safe_for_auto_execute(&value, &config).unwrap());
+    }
+}
@@ -2,7 +2,9 @@ use anyhow::Result;
 use console::style;
 use alors::{
     agent::{Agent, AgentOutput},
+    checkpoint, permissions,
     tool_collection::ToolCollection,
+    worktree::{Worktree, WorktreeAction},
 };
 use openrouter_api::{models::tool::ToolCall, types::chat::Message};
 use std::{
@@ -20,6 +22,8 @@ enum ToolInteraction {
 pub struct App {
     agent: Agent,
     stdin_receiver: mpsc::Receiver<Option<String>>,
+    /// Whether a tool changed files during the current turn, so a checkpoint is due.
+    files_changed: bool,
 }
 
 impl App {
@@ -27,16 +31,19 @@ impl App {
         Self {
             agent,
             stdin_receiver: spawn_stdin_channel(),
+            files_changed: false,
         }
     }
 
     pub async fn run(&mut self, initial_prompt: &str) -> Result<()> {
         let mut current_prompt = initial_prompt.to_string();
         let mut ctrl_c_pressed = false;
+        let mut turn_prompt = String::new();
 
         loop {
             // Prepare the prompt and display feedback before starting the agent's turn.
             if !current_prompt.is_empty() {
+                turn_prompt = current_prompt.clone();
                 let prompt_data = self.agent.prepare_prompt(&current_prompt)?;
                 display_user_message(
                     &current_prompt,
@@ -60,8 +67,7 @@ impl App {
                         biased;
                         _ = tokio::signal::ctrl_c() => {
                             if ctrl_c_pressed {
-                                println!("\nShutting down...");
-                                process::exit(0);
+                                shutdown_and_exit(&self.agent, &mut self.stdin_receiver);
                             } else {
                                 println!("\nLLM generation cancelled. Press Ctrl+C again to exit.");
                                 ctrl_c_pressed = true;
@@ -133,38 +139,103 @@ impl App {
                 }
             }
 
+            if std::mem::take(&mut self.files_changed) {
+                self.record_checkpoint(&turn_prompt);
+            }
+
             // After the agent's turn is complete, always wait for new user input.
-            print!("\x07{} ", style("user>").cyan().bold());
-            io::stdout().flush()?;
+            // REPL commands are handled here and don't start a turn.
+            loop {
+                print!("\x07{} ", style("user>").cyan().bold());
+                io::stdout().flush()?;
 
-            tokio::select! {
-                        biased;
-                        _ = tokio::signal::ctrl_c() => {
-                            if ctrl_c_pressed {
-                        println!("\nShutting down...");
-                        process::exit(0);
-                            } else {
-                                println!("\nPress Ctrl+C again to exit.");
-                                ctrl_c_pressed = true;
-                            }
+                tokio::select! {
+                    biased;
+                    _ = tokio::signal::ctrl_c() => {
+                        if ctrl_c_pressed {
+                            shutdown_and_exit(&self.agent, &mut self.stdin_receiver);
+                        } else {
+                            println!("\nPress Ctrl+C again to exit.");
+                            ctrl_c_pressed = true;
                         }
-                        line_opt = self.stdin_receiver.recv() => {
-                    match line_opt.flatten() {
-                                Some(input) => {
-                            current_prompt = input;
-                                    ctrl_c_pressed = false;
+                    }
+                    line_opt = self.stdin_receiver.recv() => {
+                        match line_opt.flatten() {
+                            Some(input) => {
+                                ctrl_c_pressed = false;
+                                if let Some(args) = checkpoint::parse_command(&input) {
+                                    self.run_checkpoint_command(args);
+                                    continue;
                                 }
-                                None => {
-                                    // Ctrl+D was pressed
-                            println!("\nShutting down...");
-                            process::exit(0);
+                                current_prompt = input;
+                            }
+                            None => {
+                                // Ctrl+D was pressed
+                                shutdown_and_exit(&self.agent, &mut self.stdin_receiver);
+                            }
                         }
                     }
                 }
+                break;
             }
         }
     }
 
+    /// Records a checkpoint after a turn that changed files, if checkpoints are enabled.
+    fn record_checkpoint(&self, prompt: &str) {
+        let Some(store) = &self.agent.checkpoints else {
+            return;
+        };
+        match store.record(prompt) {
+            Ok(Some(checkpoint)) => println!(
+                "{}",
+                style(format!(
+                    "[checkpoint {}: {}]",
+                    checkpoint.number,
+                    &checkpoint.commit[..8]
+                ))
+                .dim()
+            ),
+            Ok(None) => {}
+            Err(e) => eprintln!("{}", style(format!("[Error] Checkpoint failed: {e}")).red()),
+        }
+    }
+
+    /// Handles the `/checkpoint list|diff|restore` REPL commands.
+    /// After a restore, the conversation is told which files changed.
+    fn run_checkpoint_command(&mut self, args: &str) {
+        let Some(store) = &self.agent.checkpoints else {
+            eprintln!(
+                "{}",
+                style("Checkpoints are disabled. Enable them with `checkpoints = true`.").yellow()
+            );
+            return;
+        };
+        match checkpoint::run_command(store, args) {
+            Ok(output) => {
+                println!("{}", output.text);
+                if let Some(note) = output.note {
+                    self.agent.messages.push(Message {
+                        role: "user".to_string(),
+                        content: note,
+                        name: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    });
+                }
+            }
+            Err(e) => eprintln!("{}", style(e.to_string()).red()),
+        }
+    }
+
+    /// Stops everything the agent started in the background and finishes the worktree.
+    pub fn shutdown(&mut self) {
+        self.agent.shutdown();
+        if let Some(worktree) = &self.agent.worktree {
+            finish_worktree(worktree, &mut self.stdin_receiver);
+        }
+    }
+
     async fn process_tool_calls_interactively(
         &mut self,
         tool_calls: Vec<ToolCall>,
@@ -195,7 +266,7 @@ impl App {
                     // Inform the agent that this tool failed.
                     self.agent.messages.push(Message {
                         role: "tool".to_string(),
-                        content: error_message,
+                        content: permissions::redact_secrets(&error_message, &self.agent.config),
                         name: Some(tool_call.function_call.name.clone()),
                         tool_call_id: Some(tool_call.id.clone()),
                         tool_calls: None,
@@ -218,8 +289,7 @@ impl App {
                 tokio::select! {
                             _ = tokio::signal::ctrl_c() => {
                         if *ctrl_c_pressed {
-                            println!("\nShutting down...");
-                            process::exit(0);
+                            shutdown_and_exit(&self.agent, &mut self.stdin_receiver);
                         } else {
                             println!("\nPress Ctrl+C again to exit.");
                             *ctrl_c_pressed = true;
@@ -267,11 +337,10 @@ impl App {
                     self.agent.file_state_manager.clone(),
                 );
 
-                let result_msg = tokio::select! {
+                let (result_msg, succeeded) = tokio::select! {
                     _ = tokio::signal::ctrl_c() => {
                         if *ctrl_c_pressed {
-                            println!("\nShutting down...");
-                            process::exit(0);
+                            shutdown_and_exit(&self.agent, &mut self.stdin_receiver);
                         } else {
                             println!("\nTool execution cancelled. Press Ctrl+C again to exit.");
                             *ctrl_c_pressed = true;
@@ -293,6 +362,14 @@ impl App {
 
                 self.agent.messages.push(result_msg);
                 any_tool_run = true;
+                if succeeded
+                    && matches!(
+                        tool_call.function_call.name.as_str(),
+                        "edit_files" | "create_files"
+                    )
+                {
+                    self.files_changed = true;
+                }
             }
         }
         Ok(if any_tool_run {
@@ -303,6 +380,47 @@ impl App {
     }
 }
 
+/// Cleans up the agent's background processes, finishes the worktree and exits the program.
+fn shutdown_and_exit(agent: &Agent, stdin: &mut mpsc::Receiver<Option<String>>) -> ! {
+    println!("\nShutting down...");
+    agent.shutdown();
+    if let Some(worktree) = &agent.worktree {
+        finish_worktree(worktree, stdin);
+    }
+    process::exit(0);
+}
+
+/// Asks whether to merge, keep or discard the worktree branch and does so.
+/// A worktree without changes is removed without asking.
+fn finish_worktree(worktree: &Worktree, stdin: &mut mpsc::Receiver<Option<String>>) {
+    let action = if worktree.has_changes() {
+        print!(
+            "{} {} ",
+            style(format!("Branch '{}':", worktree.branch())).bold(),
+            WorktreeAction::PROMPT
+        );
+        io::stdout().flush().ok();
+        // The stdin task owns stdin, so the answer is read from its channel. Ctrl+D keeps the branch.
+        let answer = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(stdin.recv())
+        });
+        WorktreeAction::parse(&answer.flatten().unwrap_or_default())
+    } else {
+        WorktreeAction::Discard
+    };
+    match worktree.finish(action) {
+        Ok(message) => println!("{message}"),
+        Err(e) => eprintln!(
+            "{}",
+            style(format!(
+                "[Error] Failed to finish worktree {}: {e}",
+                worktree.path().display()
+            ))
+            .red()
+        ),
+    }
+}
+
 async fn display_user_message(
     prompt: &str,
     summaries: &[String],
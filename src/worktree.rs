@@ -0,0 +1,335 @@
+//! # Worktree Sessions
+//!
+//! With `--worktree`, the session runs in a temporary `git worktree` on a new branch, so the
+//! checked-out branch of the user is not touched while the agent works. Absolute
+//! `accessible_paths` inside the repository are rebased onto the worktree, and the process
+//! working directory moves to the same subdirectory of the worktree.
+//!
+//! At exit, the agent's changes are committed to the branch, which is then merged into the
+//! original branch, kept for later, or discarded. A worktree that is dropped without being
+//! finished, e.g. because startup failed, is discarded if it has no changes.
+
+use crate::checkpoint::git;
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What happens to the worktree branch when the session ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorktreeAction {
+    /// Merge the branch into the branch checked out in the repository, then delete it.
+    Merge,
+    /// Keep the branch, but remove the worktree directory.
+    Keep,
+    /// Delete the branch and the worktree with all their changes.
+    Discard,
+}
+
+impl WorktreeAction {
+    /// The question asked at exit.
+    pub const PROMPT: &str = "[m]erge, [k]eep or [d]iscard the worktree branch? [k]";
+
+    /// Parses an answer to `PROMPT`. Anything unrecognized keeps the branch, since that
+    /// loses nothing.
+    pub fn parse(input: &str) -> Self {
+        match input.trim().to_lowercase().as_str() {
+            "m" | "merge" => Self::Merge,
+            "d" | "discard" => Self::Discard,
+            _ => Self::Keep,
+        }
+    }
+}
+
+/// A temporary worktree that a session runs in.
+#[derive(Debug)]
+pub struct Worktree {
+    /// The root of the main working tree of the repository.
+    repo_root: PathBuf,
+    /// The root of the temporary worktree.
+    path: PathBuf,
+    branch: String,
+    /// The commit the branch was created from.
+    base: String,
+    /// Whether `finish` was called.
+    finished: AtomicBool,
+}
+
+impl Worktree {
+    /// Creates a worktree on a new branch `alors/session-<id>` from HEAD of the repository
+    /// containing `dir`. Uncommitted changes are not carried over.
+    pub fn create(dir: &Path, session_id: &str) -> Result<Self> {
+        let repo_root = git(dir, &["rev-parse", "--show-toplevel"], None)
+            .map_err(|_| anyhow!("--worktree requires a git repository: {}", dir.display()))?;
+        let repo_root = PathBuf::from(repo_root.trim());
+        let base = git(&repo_root, &["rev-parse", "--verify", "HEAD"], None)
+            .map_err(|_| anyhow!("--worktree requires a repository with at least one commit."))?
+            .trim()
+            .to_string();
+        let branch = format!("alors/session-{session_id}");
+        let path = std::env::temp_dir().join(format!("alors-worktree-{session_id}"));
+        git(
+            &repo_root,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                &branch,
+                &path.to_string_lossy(),
+                &base,
+            ],
+            None,
+        )?;
+        Ok(Self {
+            repo_root,
+            path,
+            branch,
+            base,
+            finished: AtomicBool::new(false),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// Whether the main working tree has uncommitted changes, which the worktree doesn't see.
+    pub fn repo_has_uncommitted_changes(&self) -> bool {
+        git(&self.repo_root, &["status", "--porcelain"], None)
+            .is_ok_and(|status| !status.trim().is_empty())
+    }
+
+    /// Maps a path in the main working tree to the same path in the worktree.
+    /// Paths outside the repository are returned unchanged.
+    pub fn rebase_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.repo_root) {
+            Ok(relative) => self.path.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Rebases absolute paths inside the repository onto the worktree. Relative paths stay
+    /// as they are, since they are resolved against the working directory, which moves too.
+    pub fn rebase_paths(&self, paths: &[String]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                if path.is_absolute() {
+                    self.rebase_path(path).to_string_lossy().to_string()
+                } else {
+                    path.to_string_lossy().to_string()
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the session made any changes, committed or not.
+    pub fn has_changes(&self) -> bool {
+        let uncommitted = git(&self.path, &["status", "--porcelain"], None)
+            .is_ok_and(|status| !status.trim().is_empty());
+        let committed = git(&self.path, &["rev-parse", "HEAD"], None)
+            .is_ok_and(|head| head.trim() != self.base);
+        uncommitted || committed
+    }
+
+    /// Commits the changes, then merges, keeps or discards the branch and removes the
+    /// worktree. Returns a message describing the outcome.
+    ///
+    /// If the merge fails, it is aborted and the branch is kept.
+    pub fn finish(&self, action: WorktreeAction) -> Result<String> {
+        self.finished.store(true, Ordering::SeqCst);
+        if action != WorktreeAction::Discard {
+            self.commit_changes()?;
+        }
+        self.remove_worktree()?;
+
+        match action {
+            WorktreeAction::Merge => {
+                let merged = git(
+                    &self.repo_root,
+                    &["merge", "--no-edit", "-q", &self.branch],
+                    None,
+                );
+                match merged {
+                    Ok(_) => {
+                        git(&self.repo_root, &["branch", "-q", "-d", &self.branch], None)?;
+                        Ok(format!("Merged branch '{}'.", self.branch))
+                    }
+                    Err(e) => {
+                        let _ = git(&self.repo_root, &["merge", "--abort"], None);
+                        Ok(format!(
+                            "Merging branch '{}' failed, the branch was kept. {e}",
+                            self.branch
+                        ))
+                    }
+                }
+            }
+            WorktreeAction::Keep => Ok(format!("Kept branch '{}'.", self.branch)),
+            WorktreeAction::Discard => {
+                git(&self.repo_root, &["branch", "-q", "-D", &self.branch], None)?;
+                Ok(format!("Discarded branch '{}'.", self.branch))
+            }
+        }
+    }
+
+    fn commit_changes(&self) -> Result<()> {
+        git(&self.path, &["add", "--all"], None)?;
+        let staged = git(&self.path, &["diff", "--cached", "--name-only"], None)?;
+        if !staged.trim().is_empty() {
+            git(
+                &self.path,
+                &["commit", "-q", "--no-verify", "-m", "alors session changes"],
+                None,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn remove_worktree(&self) -> Result<()> {
+        git(
+            &self.repo_root,
+            &[
+                "worktree",
+                "remove",
+                "--force",
+                &self.path.to_string_lossy(),
+            ],
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        if self.finished.load(Ordering::SeqCst) {
+            return;
+        }
+        if self.has_changes() {
+            eprintln!(
+                "The worktree {} on branch '{}' has changes and was kept.",
+                self.path.display(),
+                self.branch
+            );
+        } else {
+            let _ = self.finish(WorktreeAction::Discard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    fn setup_repo() -> tempfile::TempDir {
+        let tmp_dir = Builder::new().prefix("test-worktree").tempdir().unwrap();
+        let dir = tmp_dir.path();
+        git(dir, &["init", "-q", "-b", "main"], None).unwrap();
+        git(dir, &["config", "user.name", "Tester"], None).unwrap();
+        git(dir, &["config", "user.email", "tester@example.com"], None).unwrap();
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "fn a() {}\n").unwrap();
+        git(dir, &["add", "."], None).unwrap();
+        git(dir, &["commit", "-q", "-m", "Initial commit"], None).unwrap();
+        tmp_dir
+    }
+
+    fn unique_id(name: &str) -> String {
+        format!("{name}-{}", std::process::id())
+    }
+
+    #[test]
+    fn test_rebase_paths() {
+        let tmp_dir = setup_repo();
+        let root = tmp_dir.path().canonicalize().unwrap();
+        let worktree = Worktree::create(&root, &unique_id("rebase")).unwrap();
+
+        let paths = worktree.rebase_paths(&[
+            ".".to_string(),
+            root.join("src").to_string_lossy().to_string(),
+            "/usr/include".to_string(),
+        ]);
+
+        assert_eq!(paths[0], ".");
+        assert_eq!(Path::new(&paths[1]), worktree.path().join("src"));
+        assert_eq!(paths[2], "/usr/include");
+        assert!(worktree.path().join("src/lib.rs").exists());
+        worktree.finish(WorktreeAction::Discard).unwrap();
+    }
+
+    #[test]
+    fn test_merge_changes_into_original_branch() {
+        let tmp_dir = setup_repo();
+        let root = tmp_dir.path();
+        let worktree = Worktree::create(root, &unique_id("merge")).unwrap();
+        assert!(!worktree.has_changes());
+
+        fs::write(worktree.path().join("src/lib.rs"), "fn a() { 1 }\n").unwrap();
+        fs::write(worktree.path().join("new.rs"), "fn b() {}\n").unwrap();
+        // The original working tree is untouched until the merge.
+        assert_eq!(
+            fs::read_to_string(root.join("src/lib.rs")).unwrap(),
+            "fn a() {}\n"
+        );
+        assert!(worktree.has_changes());
+
+        let message = worktree.finish(WorktreeAction::Merge).unwrap();
+
+        assert_eq!(message, format!("Merged branch '{}'.", worktree.branch()));
+        assert_eq!(
+            fs::read_to_string(root.join("src/lib.rs")).unwrap(),
+            "fn a() { 1 }\n"
+        );
+        assert!(root.join("new.rs").exists());
+        assert!(!worktree.path().exists());
+        assert!(git(root, &["rev-parse", "--verify", worktree.branch()], None).is_err());
+    }
+
+    #[test]
+    fn test_keep_and_discard() {
+        let tmp_dir = setup_repo();
+        let root = tmp_dir.path();
+
+        let kept = Worktree::create(root, &unique_id("keep")).unwrap();
+        fs::write(kept.path().join("new.rs"), "fn b() {}\n").unwrap();
+        kept.finish(WorktreeAction::Keep).unwrap();
+        let files = git(root, &["ls-tree", "-r", "--name-only", kept.branch()], None).unwrap();
+        assert!(files.contains("new.rs"));
+        assert!(!root.join("new.rs").exists());
+
+        let discarded = Worktree::create(root, &unique_id("discard")).unwrap();
+        fs::write(discarded.path().join("new.rs"), "fn b() {}\n").unwrap();
+        discarded.finish(WorktreeAction::Discard).unwrap();
+        assert!(git(root, &["rev-parse", "--verify", discarded.branch()], None).is_err());
+        assert!(!discarded.path().exists());
+    }
+
+    #[test]
+    fn test_unfinished_worktree_is_discarded_on_drop() {
+        let tmp_dir = setup_repo();
+        let root = tmp_dir.path();
+        let worktree = Worktree::create(root, &unique_id("drop")).unwrap();
+        let path = worktree.path().to_path_buf();
+        let branch = worktree.branch().to_string();
+
+        drop(worktree);
+
+        assert!(!path.exists());
+        assert!(git(root, &["rev-parse", "--verify", &branch], None).is_err());
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(WorktreeAction::parse("m"), WorktreeAction::Merge);
+        assert_eq!(WorktreeAction::parse(" Discard "), WorktreeAction::Discard);
+        assert_eq!(WorktreeAction::parse(""), WorktreeAction::Keep);
+        assert_eq!(WorktreeAction::parse("x"), WorktreeAction::Keep);
+    }
+}
@@ -18,7 +18,7 @@ async fn test_agent_uses_file_editor_tool() -> Result<()> {
     // 2. Initialize the agent and its components
     let config = Config::default();
     let mut tool_collection = ToolCollection::new();
-    tool_collection.register(Box::new(FileEditorTool));
+    tool_collection.register(Box::new(FileEditorTool::default()));
     let tool_collection = Arc::new(tool_collection);
 
     let mut agent = Agent::new(config, None, tool_collection);